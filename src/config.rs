@@ -1,8 +1,12 @@
 use std::time::Duration;
 
+use time::OffsetDateTime;
 use twitch_api::types::NicknameRef;
 
-use crate::watcher::youtube::api::YoutubeHandleRef;
+use crate::{
+    model::{money::CurrencyCodeRef, Creator, StreamingService},
+    watcher::youtube::api::YoutubeHandleRef,
+};
 
 #[derive(Debug)]
 pub struct CreatorNames {
@@ -13,19 +17,130 @@ pub struct CreatorNames {
 #[derive(Debug, Clone, Copy)]
 pub struct CampaignConfig {
     pub id: u32,
+    /// Currency to assume if tiltify does not provide one
+    pub currency: &'static CurrencyCodeRef,
+    /// Case insensitive keywords in the title of a creator's stream for the campaign,
+    /// used to tell it apart when they are running multiple streams
+    pub stream_keywords: &'static [&'static str],
+    /// Whole amounts raised in the campaign's currency worth celebrating when passed
+    pub milestones: &'static [u32],
+}
+
+/// A reference to a creator on the roster
+#[derive(Debug, Clone, Copy)]
+pub enum CreatorHandle {
+    Twitch(&'static NicknameRef),
+    Youtube(&'static YoutubeHandleRef),
+}
+
+impl CreatorHandle {
+    pub fn service(self) -> StreamingService {
+        match self {
+            CreatorHandle::Twitch(_) => StreamingService::Twitch,
+            CreatorHandle::Youtube(_) => StreamingService::Youtube,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CreatorHandle::Twitch(nickname) => nickname.as_str(),
+            CreatorHandle::Youtube(handle) => handle.as_str(),
+        }
+    }
+
+    /// Whether the handle refers to the given creator
+    pub fn matches(self, creator: &Creator) -> bool {
+        creator.service == self.service() && creator.handle.eq_ignore_ascii_case(self.as_str())
+    }
+}
+
+/// A person or group behind one or more creator accounts
+#[derive(Debug)]
+pub struct PersonConfig {
+    pub name: &'static str,
+    pub accounts: &'static [CreatorHandle],
+}
+
+/// The wording of announcements, `{placeholders}` are filled in from the announcement
+#[derive(Debug, Clone, Copy)]
+pub struct AnnouncementTemplates {
+    /// `{name}`, `{title}`, `{category}`, `{link}`, `{campaign}` and `{total}`
+    pub live: &'static str,
+    /// `{milestone}`, `{campaign}` and `{total}`
+    pub milestone: &'static str,
+    /// `{goal}`, `{campaign}` and `{total}`
+    pub goal_reached: &'static str,
+}
+
+#[derive(Debug)]
+pub struct SocialConfig {
+    pub mastodon: AnnouncementTemplates,
+    pub bluesky: AnnouncementTemplates,
+}
+
+/// A time slot in the organizer's lineup
+#[derive(Debug)]
+pub struct LineupSlotConfig {
+    pub creator: CreatorHandle,
+    pub starts_at: OffsetDateTime,
+    pub ends_at: OffsetDateTime,
 }
 
 #[derive(Debug)]
 pub struct Config {
     pub campaign: CampaignConfig,
     pub creators: CreatorNames,
+    pub lineup: &'static [LineupSlotConfig],
+    /// Creators with accounts on multiple services, creators not listed are shown on their own
+    pub people: &'static [PersonConfig],
+    /// Creators who do not want their streams announced, on social media or to followers
+    pub announcement_opt_out: &'static [CreatorHandle],
+    pub social: SocialConfig,
     pub refresh_period: Duration,
 }
 
 pub static CONFIG: Config = Config {
     refresh_period: Duration::from_secs(10 * 60), // 10 minutes
 
-    campaign: CampaignConfig { id: 468510 },
+    campaign: CampaignConfig {
+        id: 468510,
+        currency: CurrencyCodeRef::from_str("USD"),
+        stream_keywords: &["creators for a cause"],
+        milestones: &[1_000, 2_500, 5_000, 10_000, 25_000, 50_000],
+    },
+
+    // Slots should reference creators on the roster, e.g.
+    // LineupSlotConfig {
+    //     creator: CreatorHandle::Twitch(NicknameRef::from_str("kkywi")),
+    //     starts_at: time::macros::datetime!(2023-01-01 14:00 UTC),
+    //     ends_at: time::macros::datetime!(2023-01-01 16:00 UTC),
+    // },
+    lineup: &[],
+
+    // People with accounts on multiple services, e.g.
+    // PersonConfig {
+    //     name: "Gathe",
+    //     accounts: &[
+    //         CreatorHandle::Twitch(NicknameRef::from_str("gathe_")),
+    //         CreatorHandle::Youtube(YoutubeHandleRef::from_str("@therealgathe")),
+    //     ],
+    // },
+    people: &[],
+
+    announcement_opt_out: &[],
+
+    social: SocialConfig {
+        mastodon: AnnouncementTemplates {
+            live: "{name} is now live for {campaign}: {title}\n\n{link}\n\n#CreatorsForACause",
+            milestone: "{campaign} has raised over {milestone}! Thank you to everyone who has donated, {total} and counting\n\n#CreatorsForACause",
+            goal_reached: "{campaign} has reached its goal of {goal}! Thank you to everyone who made it happen\n\n#CreatorsForACause",
+        },
+        bluesky: AnnouncementTemplates {
+            live: "{name} is now live for {campaign}: {title}\n\n{link}",
+            milestone: "{campaign} has raised over {milestone}! Thank you to everyone who has donated, {total} and counting",
+            goal_reached: "{campaign} has reached its goal of {goal}! Thank you to everyone who made it happen",
+        },
+    },
 
     // TODO: FIXME: User IDs!!!!!!!!
     creators: CreatorNames {
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use tracing::{debug, trace};
+
+use crate::{
+    config::CampaignConfig,
+    model::{money::Money, Creator, CreatorKey, LiveStreamDetails},
+    watcher::WatcherData,
+};
+
+/// Events buffered for each subscriber before the slowest start missing them
+pub const EVENT_BUFFER: usize = 64;
+
+/// Something that changed between two publishes of the watched data
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    #[serde(with = "time::serde::rfc3339")]
+    pub time: OffsetDateTime,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Change {
+    /// The creator started streaming, with their campaign stream if they have multiple
+    WentLive {
+        creator: CreatorKey,
+        stream: LiveStreamDetails,
+    },
+    WentOffline {
+        creator: CreatorKey,
+    },
+    TitleChanged {
+        creator: CreatorKey,
+        href: String,
+        previous: String,
+        title: String,
+    },
+    CategoryChanged {
+        creator: CreatorKey,
+        href: String,
+        previous: Option<String>,
+        category: Option<String>,
+    },
+    /// A stream reached more concurrent viewers than ever before
+    ViewersPeaked {
+        creator: CreatorKey,
+        href: String,
+        viewers: u32,
+    },
+    AmountRaised {
+        previous: Money,
+        total: Money,
+    },
+    /// The total passed one of the configured milestones
+    MilestoneReached {
+        milestone: Money,
+        total: Money,
+    },
+    GoalReached {
+        goal: Money,
+        total: Money,
+    },
+}
+
+/// Turns consecutive publishes into change events, and sends them to every subscriber
+pub struct ChangeEmitter {
+    sender: broadcast::Sender<ChangeEvent>,
+    /// The highest viewer count of each live stream, by its start time and href, as Twitch
+    /// links to the channel rather than the stream
+    peak_viewers: HashMap<(OffsetDateTime, String), u32>,
+}
+
+impl ChangeEmitter {
+    pub fn new(sender: broadcast::Sender<ChangeEvent>) -> Self {
+        Self {
+            sender,
+            peak_viewers: HashMap::new(),
+        }
+    }
+
+    /// Emit what changed, previous data restored from a snapshot still counts, so restarts
+    /// do not announce everything again
+    pub fn emit(&mut self, previous: &WatcherData, current: &WatcherData, config: &CampaignConfig) {
+        let changes = self.changes(previous, current, config);
+
+        if changes.is_empty() {
+            trace!("no change events");
+            return;
+        }
+
+        for change in changes {
+            debug!(?change, "change event");
+
+            // Nobody listening is fine, the event simply goes nowhere
+            let _ = self.sender.send(ChangeEvent {
+                time: current.updated,
+                change,
+            });
+        }
+    }
+
+    fn changes(
+        &mut self,
+        previous: &WatcherData,
+        current: &WatcherData,
+        config: &CampaignConfig,
+    ) -> Vec<Change> {
+        let mut changes = Vec::new();
+
+        for creator in current.creators.iter() {
+            let previous = previous
+                .creators
+                .iter()
+                .find(|previous| previous.is(creator.service, &creator.id));
+
+            self.creator_changes(previous, creator, &mut changes);
+        }
+
+        // Creators who dropped out of the roster are no longer watched, and would otherwise
+        // stay live for every consumer
+        changes.extend(
+            previous
+                .creators
+                .iter()
+                .filter(|creator| creator.is_live())
+                .filter(|creator| {
+                    !current
+                        .creators
+                        .iter()
+                        .any(|current| current.is(creator.service, &creator.id))
+                })
+                .map(|creator| Change::WentOffline {
+                    creator: creator.key(),
+                }),
+        );
+
+        // Streams that ended can never peak again
+        self.peak_viewers.retain(|key, _| {
+            current
+                .creators
+                .iter()
+                .flat_map(|creator| &creator.streams)
+                .any(|stream| stream_key(stream) == *key)
+        });
+
+        let previous_total = &previous.totals.total;
+        let total = &current.totals.total;
+
+        // Amounts in different currencies cannot be compared
+        if previous_total.currency != total.currency || total.amount <= previous_total.amount {
+            return changes;
+        }
+
+        changes.push(Change::AmountRaised {
+            previous: previous_total.clone(),
+            total: total.clone(),
+        });
+
+        let passed = |amount: Decimal| previous_total.amount < amount && amount <= total.amount;
+
+        changes.extend(
+            config
+                .milestones
+                .iter()
+                .map(|&milestone| Money::new(milestone.into(), total.currency.clone()))
+                .filter(|milestone| passed(milestone.amount))
+                .map(|milestone| Change::MilestoneReached {
+                    milestone,
+                    total: total.clone(),
+                }),
+        );
+
+        let goal = &current.totals.goal;
+
+        if goal.currency == total.currency && !goal.amount.is_zero() && passed(goal.amount) {
+            changes.push(Change::GoalReached {
+                goal: goal.clone(),
+                total: total.clone(),
+            });
+        }
+
+        changes
+    }
+
+    fn creator_changes(
+        &mut self,
+        previous: Option<&Creator>,
+        creator: &Creator,
+        changes: &mut Vec<Change>,
+    ) {
+        let was_live = previous.map_or(false, Creator::is_live);
+
+        match (was_live, creator.streams.first()) {
+            (false, Some(stream)) => {
+                changes.push(Change::WentLive {
+                    creator: creator.key(),
+                    stream: stream.clone(),
+                });
+            }
+            (true, None) => {
+                changes.push(Change::WentOffline {
+                    creator: creator.key(),
+                });
+            }
+            _ => {}
+        }
+
+        for stream in &creator.streams {
+            // Streams are matched up by their link and start, since creators can run several
+            // at once and channel links are reused by the next stream
+            let previous = previous
+                .into_iter()
+                .flat_map(|previous| &previous.streams)
+                .find(|previous| {
+                    previous.href == stream.href && previous.start_time == stream.start_time
+                });
+
+            let Some(previous) = previous else {
+                // A new stream starts at its first count, rather than peaking straight away
+                if let Some(viewers) = stream.viewers {
+                    self.peak_viewers.insert(stream_key(stream), viewers);
+                }
+
+                continue;
+            };
+
+            if previous.title != stream.title {
+                changes.push(Change::TitleChanged {
+                    creator: creator.key(),
+                    href: stream.href.clone(),
+                    previous: previous.title.clone(),
+                    title: stream.title.clone(),
+                });
+            }
+
+            if previous.category != stream.category {
+                changes.push(Change::CategoryChanged {
+                    creator: creator.key(),
+                    href: stream.href.clone(),
+                    previous: previous.category.clone(),
+                    category: stream.category.clone(),
+                });
+            }
+
+            if let Some(viewers) = stream.viewers {
+                let peak = self
+                    .peak_viewers
+                    .entry(stream_key(stream))
+                    .or_insert_with(|| previous.viewers.unwrap_or_default());
+
+                if viewers > *peak {
+                    *peak = viewers;
+
+                    changes.push(Change::ViewersPeaked {
+                        creator: creator.key(),
+                        href: stream.href.clone(),
+                        viewers,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn stream_key(stream: &LiveStreamDetails) -> (OffsetDateTime, String) {
+    (stream.start_time, stream.href.clone())
+}
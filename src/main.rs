@@ -3,27 +3,48 @@
 use std::{borrow::Cow, env, io::ErrorKind, net::SocketAddr, sync::Arc};
 
 use color_eyre::eyre::Context;
+use notifications::{
+    bluesky::BlueskyEnvironment, discord::DiscordEnvironment, mastodon::MastodonEnvironment,
+    web_push::WebPushEnvironment, webhooks::OutgoingWebhooksEnvironment,
+};
 use prometheus_client::registry::{Registry, Unit};
 use sentry::SessionMode;
-use serde::Deserialize;
-use tokio::sync::watch;
-use tracing::trace;
-use watcher::WatcherEnvironment;
+use serde::{de::DeserializeOwned, Deserialize};
+use store::StoreEnvironment;
+use tokio::sync::{broadcast, mpsc, watch};
+use tracing::{info, trace};
+use watcher::{youtube::websub::WebSubEnvironment, WatcherEnvironment};
+use web::WebEnvironment;
 
 use crate::{
     config::CONFIG,
+    events::{ChangeEmitter, EVENT_BUFFER},
     metrics::{
         gauge_info::GaugeInfo,
         metrics_server,
-        types::{LiveCreatorsMetric, WatcherRefreshPeriodMetric, YoutubeQuotaUsageMetric},
+        types::{
+            outgoing_webhook_latency, LiveCreatorsMetric, OutgoingWebhookDeliveriesMetric,
+            OutgoingWebhookLatencyMetric, WatcherRefreshPeriodMetric, YoutubeQuotaUsageMetric,
+        },
+    },
+    notifications::{
+        bluesky::bluesky_notifier,
+        discord::discord_notifier,
+        mastodon::mastodon_notifier,
+        web_push::{web_push_notifier, Vapid},
+        webhooks::webhook_sender,
     },
-    watcher::{live_watcher, WatcherDataReceive},
-    web::web_server,
+    store::{event_log, history_recorder, snapshot, Store},
+    watcher::{live_watcher, youtube::websub::websub_subscriber, WatcherDataReceive},
+    web::{web_server, WebSubCallback},
 };
 
 mod config;
+mod events;
 mod metrics;
 mod model;
+mod notifications;
+mod store;
 mod tracing_setup;
 mod watcher;
 mod web;
@@ -46,6 +67,70 @@ struct Environment {
 
     #[serde(flatten)]
     watcher: WatcherEnvironment,
+
+    #[serde(flatten)]
+    web: WebEnvironment,
+
+    #[serde(flatten)]
+    store: StoreEnvironment,
+
+    /// Log what would be posted to mastodon and bluesky, instead of posting it
+    #[serde(default)]
+    social_dry_run: bool,
+}
+
+/// The environment of an optional feature, which is enabled by setting its variables
+trait OptionalEnvironment: DeserializeOwned {
+    /// Every variable the feature reads, setting any of them enables it
+    const VARIABLES: &'static [&'static str];
+}
+
+impl OptionalEnvironment for WebSubEnvironment {
+    const VARIABLES: &'static [&'static str] =
+        &["YOUTUBE_WEBSUB_CALLBACK_URL", "YOUTUBE_WEBSUB_SECRET"];
+}
+
+impl OptionalEnvironment for DiscordEnvironment {
+    const VARIABLES: &'static [&'static str] = &["DISCORD_WEBHOOK_URLS"];
+}
+
+impl OptionalEnvironment for OutgoingWebhooksEnvironment {
+    // The dead letter path is set on every deploy, webhooks or not
+    const VARIABLES: &'static [&'static str] = &["OUTGOING_WEBHOOKS_PATH"];
+}
+
+impl OptionalEnvironment for MastodonEnvironment {
+    const VARIABLES: &'static [&'static str] = &["MASTODON_INSTANCE_URL", "MASTODON_ACCESS_TOKEN"];
+}
+
+impl OptionalEnvironment for BlueskyEnvironment {
+    const VARIABLES: &'static [&'static str] = &[
+        "BLUESKY_IDENTIFIER",
+        "BLUESKY_APP_PASSWORD",
+        "BLUESKY_SERVICE_URL",
+    ];
+}
+
+impl OptionalEnvironment for WebPushEnvironment {
+    const VARIABLES: &'static [&'static str] = &["VAPID_PRIVATE_KEY", "VAPID_SUBJECT"];
+}
+
+/// Load the environment of an optional feature on its own, as flattening an `Option` into
+/// the rest would quietly disable the feature if a variable is missing or malformed
+fn optional_environment<E: OptionalEnvironment>() -> color_eyre::Result<Option<E>> {
+    if !E::VARIABLES
+        .iter()
+        .any(|variable| env::var_os(variable).is_some())
+    {
+        return Ok(None);
+    }
+
+    envy::from_env().map(Some).wrap_err_with(|| {
+        format!(
+            "incomplete or invalid configuration in {}",
+            E::VARIABLES.join(", ")
+        )
+    })
 }
 
 // Since fly.io is a one core machine, we only need the current thread
@@ -154,18 +239,242 @@ async fn async_main() -> color_eyre::Result<()> {
         youtube_quota_usage
     };
 
-    let (watcher_sender, watcher_receiver) = watch::channel::<WatcherDataReceive>(None);
+    let outgoing_webhook_deliveries = {
+        let outgoing_webhook_deliveries = OutgoingWebhookDeliveriesMetric::default();
+        registry.register(
+            "outgoing_webhook_deliveries",
+            "Attempts at delivering outgoing webhooks, by their outcome",
+            outgoing_webhook_deliveries.clone(),
+        );
+
+        outgoing_webhook_deliveries
+    };
+
+    let outgoing_webhook_latency = {
+        let outgoing_webhook_latency = OutgoingWebhookLatencyMetric::new_with_constructor(
+            outgoing_webhook_latency as fn() -> _,
+        );
+        registry.register_with_unit(
+            "outgoing_webhook_latency",
+            "The time taken by partners to respond to outgoing webhooks",
+            Unit::Seconds,
+            outgoing_webhook_latency.clone(),
+        );
+
+        outgoing_webhook_latency
+    };
+
+    let vapid = optional_environment::<WebPushEnvironment>()?
+        .map(Vapid::new)
+        .transpose()
+        .wrap_err("invalid web push configuration")?;
+    let push_public_key = vapid.as_ref().map(Vapid::public_key);
+
+    let store = Store::open(&environment.store.database_path)
+        .wrap_err("failed to open the persistent store")?;
+
+    let snapshot_path = environment.store.snapshot_path();
+    let restored = snapshot::load(&snapshot_path).await.map(Arc::new);
+    let event_log_path = environment.store.event_log_path;
+
+    let (watcher_sender, watcher_receiver) = watch::channel::<WatcherDataReceive>(restored);
+    let (change_event_sender, change_event_receiver) = broadcast::channel(EVENT_BUFFER);
+    let (history_summary_sender, history_summary_receiver) = watch::channel(Default::default());
+    let (tiltify_event_sender, tiltify_event_receiver) = mpsc::channel(16);
+    let (youtube_notification_sender, youtube_notification_receiver) = mpsc::channel(16);
+
+    let youtube_websub_environment = optional_environment::<WebSubEnvironment>()?;
+    let (websub_lease_sender, websub_lease_receiver) = mpsc::channel(16);
+    let websub_callback =
+        youtube_websub_environment
+            .as_ref()
+            .map(|youtube_websub| WebSubCallback {
+                secret: youtube_websub.secret.clone(),
+                leases: websub_lease_sender,
+            });
+
+    let youtube_websub = {
+        let reqwest_client = reqwest_client.clone();
+        let watcher_receiver = watcher_receiver.clone();
+
+        async move {
+            match youtube_websub_environment {
+                Some(youtube_websub) => {
+                    websub_subscriber(
+                        reqwest_client,
+                        youtube_websub,
+                        watcher_receiver,
+                        websub_lease_receiver,
+                    )
+                    .await
+                }
+                None => info!("no youtube websub configuration provided, relying on polling"),
+            }
+        }
+    };
+
+    let discord_environment = optional_environment::<DiscordEnvironment>()?;
+    let discord_notifier = {
+        let reqwest_client = reqwest_client.clone();
+        let store = store.clone();
+        let events = change_event_sender.subscribe();
+        let watcher_receiver = watcher_receiver.clone();
+
+        async move {
+            match discord_environment {
+                Some(discord) => {
+                    discord_notifier(
+                        reqwest_client,
+                        discord,
+                        &CONFIG,
+                        store,
+                        events,
+                        watcher_receiver,
+                    )
+                    .await
+                }
+                None => info!("no discord configuration provided, not announcing to discord"),
+            }
+        }
+    };
+
+    let mastodon_environment = optional_environment::<MastodonEnvironment>()?;
+    let mastodon_notifier = {
+        let reqwest_client = reqwest_client.clone();
+        let store = store.clone();
+        let events = change_event_sender.subscribe();
+        let watcher_receiver = watcher_receiver.clone();
+        let dry_run = environment.social_dry_run;
+
+        async move {
+            match mastodon_environment {
+                Some(mastodon) => {
+                    mastodon_notifier(
+                        reqwest_client,
+                        mastodon,
+                        &CONFIG,
+                        store,
+                        events,
+                        watcher_receiver,
+                        dry_run,
+                    )
+                    .await
+                }
+                None => info!("no mastodon configuration provided, not announcing to mastodon"),
+            }
+        }
+    };
+
+    let bluesky_environment = optional_environment::<BlueskyEnvironment>()?;
+    let bluesky_notifier = {
+        let reqwest_client = reqwest_client.clone();
+        let store = store.clone();
+        let events = change_event_sender.subscribe();
+        let watcher_receiver = watcher_receiver.clone();
+        let dry_run = environment.social_dry_run;
+
+        async move {
+            match bluesky_environment {
+                Some(bluesky) => {
+                    bluesky_notifier(
+                        reqwest_client,
+                        bluesky,
+                        &CONFIG,
+                        store,
+                        events,
+                        watcher_receiver,
+                        dry_run,
+                    )
+                    .await
+                }
+                None => info!("no bluesky configuration provided, not announcing to bluesky"),
+            }
+        }
+    };
+
+    let web_push_notifier = {
+        let reqwest_client = reqwest_client.clone();
+        let store = store.clone();
+        let events = change_event_sender.subscribe();
+        let watcher_receiver = watcher_receiver.clone();
+
+        async move {
+            match vapid {
+                Some(vapid) => {
+                    web_push_notifier(
+                        reqwest_client,
+                        vapid,
+                        &CONFIG,
+                        store,
+                        events,
+                        watcher_receiver,
+                    )
+                    .await
+                }
+                None => info!("no vapid key provided, not sending web push notifications"),
+            }
+        }
+    };
+
+    let outgoing_webhooks_environment = optional_environment::<OutgoingWebhooksEnvironment>()?;
+    let outgoing_webhooks = {
+        let reqwest_client = reqwest_client.clone();
+        let events = change_event_sender.subscribe();
+
+        async move {
+            match outgoing_webhooks_environment {
+                Some(outgoing_webhooks) => {
+                    webhook_sender(
+                        reqwest_client,
+                        outgoing_webhooks,
+                        events,
+                        outgoing_webhook_deliveries,
+                        outgoing_webhook_latency,
+                    )
+                    .await
+                }
+                None => info!("no outgoing webhooks configured"),
+            }
+        }
+    };
 
     tokio::join!(
+        youtube_websub,
+        discord_notifier,
+        mastodon_notifier,
+        bluesky_notifier,
+        web_push_notifier,
+        outgoing_webhooks,
+        snapshot::snapshot_writer(&snapshot_path, watcher_receiver.clone()),
+        event_log::event_logger(&event_log_path, change_event_receiver),
+        history_recorder(
+            store.clone(),
+            &CONFIG,
+            watcher_receiver.clone(),
+            history_summary_sender
+        ),
         live_watcher(
             reqwest_client,
             environment.watcher,
             &CONFIG,
             watcher_sender,
+            ChangeEmitter::new(change_event_sender),
+            tiltify_event_receiver,
+            youtube_notification_receiver,
             live_creators,
             youtube_quota_usage
         ),
-        web_server(environment.listen, watcher_receiver),
+        web_server(
+            environment.listen,
+            environment.web,
+            watcher_receiver,
+            tiltify_event_sender,
+            websub_callback,
+            youtube_notification_sender,
+            store,
+            history_summary_receiver,
+            push_public_key
+        ),
         metrics_server(Arc::new(registry))
     );
 
@@ -16,8 +16,13 @@ pub mod types {
     use std::sync::atomic::{AtomicI64, AtomicU64};
 
     use prometheus_client::{
-        encoding::EncodeLabelSet,
-        metrics::{counter::Counter, family::Family, gauge::Gauge},
+        encoding::{EncodeLabelSet, EncodeLabelValue},
+        metrics::{
+            counter::Counter,
+            family::Family,
+            gauge::Gauge,
+            histogram::{exponential_buckets, Histogram},
+        },
     };
 
     use crate::model::StreamingService;
@@ -25,6 +30,15 @@ pub mod types {
     pub type WatcherRefreshPeriodMetric = Gauge<i64, AtomicI64>;
     pub type LiveCreatorsMetric = Family<StreamingServiceMetricKey, Gauge<i64, AtomicI64>>;
     pub type YoutubeQuotaUsageMetric = Counter<u64, AtomicU64>;
+    pub type OutgoingWebhookDeliveriesMetric =
+        Family<OutgoingWebhookDeliveryKey, Counter<u64, AtomicU64>>;
+    pub type OutgoingWebhookLatencyMetric =
+        Family<OutgoingWebhookKey, Histogram, fn() -> Histogram>;
+
+    /// Response times from 50ms up to around 25 seconds
+    pub fn outgoing_webhook_latency() -> Histogram {
+        Histogram::new(exponential_buckets(0.05, 2.0, 10))
+    }
 
     #[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
     pub struct StreamingServiceMetricKey {
@@ -32,6 +46,26 @@ pub mod types {
         pub username: String,
         pub id: String,
     }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+    pub struct OutgoingWebhookKey {
+        pub webhook: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+    pub struct OutgoingWebhookDeliveryKey {
+        pub webhook: String,
+        pub outcome: DeliveryOutcome,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EncodeLabelValue)]
+    pub enum DeliveryOutcome {
+        Delivered,
+        /// An attempt failed, and will be tried again
+        Retried,
+        /// Every attempt failed, and the payload was written to the dead letter log
+        DeadLettered,
+    }
 }
 
 pub async fn metrics_server(registry: Arc<Registry>) {
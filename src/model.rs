@@ -1,13 +1,21 @@
-use std::cmp;
+use std::{cmp, fmt, str::FromStr};
 
 use prometheus_client::encoding::EncodeLabelValue;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use time::OffsetDateTime;
 
+use tracing::warn;
+
+use self::money::Money;
+
 pub mod javascript_unix_timestamp;
+pub mod lineup;
+pub mod money;
+pub mod person;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Creator {
     /// The internal, unchanging ID used by the respective service
     pub id: String,
@@ -15,70 +23,240 @@ pub struct Creator {
     pub handle: String,
     pub href: String,
     pub icon_url: String,
-    pub stream: Option<LiveStreamDetails>,
+    /// Concurrent live streams, with the campaign's stream first
+    pub streams: Vec<LiveStreamDetails>,
+    /// Scheduled streams that have not started yet
+    pub upcoming: Vec<ScheduledStream>,
+    /// A fundraiser run natively through the streaming service
+    pub charity: Option<CharityFundraiser>,
+    pub stats: Option<ChannelStats>,
     pub service: StreamingService,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EncodeLabelValue, Serialize)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    EncodeLabelValue,
+    Serialize,
+    Deserialize,
+)]
 #[serde(rename_all = "lowercase")]
 pub enum StreamingService {
     Twitch,
     Youtube,
 }
 
-impl Eq for Creator {}
-impl PartialEq for Creator {
-    fn eq(&self, other: &Self) -> bool {
-        self.display_name == other.display_name && self.stream.is_some() == other.stream.is_some()
+impl fmt::Display for StreamingService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamingService::Twitch => write!(f, "twitch"),
+            StreamingService::Youtube => write!(f, "youtube"),
+        }
     }
 }
 
-impl Ord for Creator {
-    fn cmp(&self, other: &Self) -> cmp::Ordering {
-        match (self.stream.is_some(), other.stream.is_some()) {
-            (true, false) => cmp::Ordering::Less,
-            (false, true) => cmp::Ordering::Greater,
-            (true, true) | (false, false) => self.display_name.cmp(&other.display_name),
+#[derive(Debug, Error)]
+#[error("unknown streaming service: {0}")]
+pub struct UnknownStreamingService(String);
+
+impl FromStr for StreamingService {
+    type Err = UnknownStreamingService;
+
+    fn from_str(service: &str) -> Result<Self, Self::Err> {
+        match service {
+            "twitch" => Ok(StreamingService::Twitch),
+            "youtube" => Ok(StreamingService::Youtube),
+            service => Err(UnknownStreamingService(service.to_owned())),
         }
     }
 }
-impl PartialOrd for Creator {
-    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
-        Some(self.cmp(other))
+
+/// The identity of a creator, IDs are only unique within a single service
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct CreatorKey {
+    pub service: StreamingService,
+    pub id: String,
+}
+
+impl CreatorKey {
+    /// Find the creator this key refers to
+    pub fn resolve<'c>(&self, creators: &'c [Creator]) -> Option<&'c Creator> {
+        creators
+            .iter()
+            .find(|creator| creator.is(self.service, &self.id))
+    }
+}
+
+impl fmt::Display for CreatorKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.service, self.id)
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
+impl Creator {
+    pub fn key(&self) -> CreatorKey {
+        CreatorKey {
+            service: self.service,
+            id: self.id.clone(),
+        }
+    }
+
+    pub fn is(&self, service: StreamingService, id: &str) -> bool {
+        self.service == service && self.id == id
+    }
+
+    pub fn is_live(&self) -> bool {
+        !self.streams.is_empty()
+    }
+
+    /// Mark the streams whose title contains any of the keywords as the campaign's streams,
+    /// and order them first. A creator's only stream is assumed to be for the campaign
+    pub fn pin_campaign_streams(&mut self, keywords: &[&str]) {
+        let single_stream = self.streams.len() == 1;
+
+        for stream in &mut self.streams {
+            let title = stream.title.to_lowercase();
+
+            stream.campaign = single_stream
+                || keywords
+                    .iter()
+                    .any(|keyword| title.contains(&keyword.to_lowercase()));
+        }
+
+        // Stable, so the order from the service is otherwise kept
+        self.streams.sort_by_key(|stream| !stream.campaign);
+    }
+}
+
+impl Creator {
+    /// Live creators first, then by name. Not an `Ord` implementation, as equality compares
+    /// everything about a creator rather than just its place in the order
+    pub fn display_order(&self, other: &Self) -> cmp::Ordering {
+        match (self.is_live(), other.is_live()) {
+            (true, false) => cmp::Ordering::Less,
+            (false, true) => cmp::Ordering::Greater,
+            (true, true) | (false, false) => self
+                .display_name
+                .cmp(&other.display_name)
+                .then_with(|| self.service.cmp(&other.service))
+                .then_with(|| self.id.cmp(&other.id)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct LiveStreamDetails {
     pub href: String,
     pub title: String,
     #[serde(with = "time::serde::rfc3339")]
     pub start_time: OffsetDateTime,
     pub viewers: Option<u32>,
+    /// The game or category being streamed
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    /// The language of the stream, as an ISO 639-1 code
+    pub language: Option<String>,
+    /// Whether the stream is intended for mature audiences
+    pub mature: bool,
+    pub thumbnail_url: Option<String>,
+    /// Whether this is the stream for the campaign, if the creator is running multiple
+    pub campaign: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ScheduledStream {
+    /// The Twitch schedule segment or YouTube video, which stays the same when rescheduled
+    pub id: String,
+    pub href: String,
+    pub title: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub start_time: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub end_time: Option<OffsetDateTime>,
+}
+
+/// The reach of a channel, each service only provides some of these
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ChannelStats {
+    pub followers: Option<u64>,
+    /// Missing if the creator has hidden their subscriber count
+    pub subscribers: Option<u64>,
+    pub views: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CharityFundraiser {
+    pub charity_name: String,
+    pub charity_website: String,
+    pub current_amount: Money,
+    pub target_amount: Option<Money>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all(deserialize = "camelCase"))]
+/// The amounts raised across all fundraising sources, in the campaign's currency
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FundraisingTotals {
+    pub tiltify: Money,
+    pub twitch_charity: Money,
+    pub total: Money,
+    pub goal: Money,
+}
+
+impl FundraisingTotals {
+    pub fn new(campaign: &Campaign, creators: &[Creator]) -> Self {
+        let currency = campaign.total_amount_raised.currency.clone();
+
+        let mut twitch_charity = Money::zero(currency.clone());
+
+        for (creator, charity) in creators
+            .iter()
+            .filter_map(|creator| Some((creator, creator.charity.as_ref()?)))
+        {
+            // Amounts are only combined if they share a currency, since conversion rates are unknown
+            match twitch_charity.checked_add(&charity.current_amount) {
+                Ok(sum) => twitch_charity = sum,
+                Err(error) => {
+                    warn!(%error, creator = %creator.handle, "excluding charity fundraiser from totals");
+                }
+            }
+        }
+
+        let total = Money::try_sum(currency, [&campaign.total_amount_raised, &twitch_charity])
+            .expect("tiltify and twitch charity totals should share a currency");
+
+        Self {
+            tiltify: campaign.total_amount_raised.clone(),
+            twitch_charity,
+            total,
+            // Charity targets are the creators' own goals, not the campaign's
+            goal: campaign.fundraiser_goal_amount.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Campaign {
     pub id: u32,
     pub name: String,
     pub slug: String,
-    #[serde(deserialize_with = "javascript_unix_timestamp::deserialize")]
-    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    #[serde(with = "time::serde::rfc3339")]
     pub starts_at: OffsetDateTime,
-    #[serde(deserialize_with = "javascript_unix_timestamp::option::deserialize")]
-    #[serde(serialize_with = "time::serde::rfc3339::option::serialize")]
+    #[serde(with = "time::serde::rfc3339::option")]
     pub ends_at: Option<OffsetDateTime>,
     pub description: String,
     pub avatar: TiltifyAvatar,
     pub cause_id: u32,
 
-    // Using floats since no math or large numbers are used, so precision is not a problem
-    pub fundraiser_goal_amount: f64,
-    pub original_fundraiser_goal: f64,
-    pub amount_raised: f64,
-    pub supporting_amount_raised: f64,
-    pub total_amount_raised: f64,
+    pub fundraiser_goal_amount: Money,
+    pub original_fundraiser_goal: Money,
+    pub amount_raised: Money,
+    pub supporting_amount_raised: Money,
+    pub total_amount_raised: Money,
 
     pub supportable: bool,
 
@@ -86,14 +264,7 @@ pub struct Campaign {
     pub team: TiltifyTeam,
 }
 
-impl Eq for Campaign {}
-impl PartialEq for Campaign {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
-    }
-}
-
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 #[serde(rename = "camel_case")]
 pub struct TiltifyAvatar {
     pub src: Url,
@@ -102,7 +273,7 @@ pub struct TiltifyAvatar {
     pub height: u32,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 #[serde(rename = "camel_case")]
 pub struct TiltifyUser {
     pub id: u32,
@@ -112,7 +283,7 @@ pub struct TiltifyUser {
     pub avatar: TiltifyAvatar,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 #[serde(rename = "camel_case")]
 pub struct TiltifyTeam {
     pub id: u32,
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::config::LineupSlotConfig;
+
+use super::{Creator, StreamingService};
+
+/// Time after the start of a slot before a streamer who has not gone live is considered missing
+const LATE_GRACE_PERIOD: Duration = Duration::minutes(15);
+
+/// A slot of the organizer's lineup, cross-checked against the creator's live status
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LineupSlot {
+    pub service: StreamingService,
+    pub handle: String,
+    /// The id of the roster creator the slot belongs to, if they could be found
+    pub creator_id: Option<String>,
+    pub display_name: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub starts_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub ends_at: OffsetDateTime,
+    /// Whether the creator was live when the slot was resolved
+    pub is_live: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SlotStatus {
+    Upcoming,
+    /// The slot has started and the creator is live
+    OnAir,
+    /// The slot has started but the creator is not live yet
+    Late,
+    /// The slot has been running for a while and the creator is still not live
+    Missing,
+    Finished,
+}
+
+impl LineupSlot {
+    pub fn resolve(slot: &LineupSlotConfig, creators: &[Creator]) -> Self {
+        let creator = creators
+            .iter()
+            .find(|creator| slot.creator.matches(creator));
+
+        Self {
+            service: slot.creator.service(),
+            handle: slot.creator.as_str().to_owned(),
+            creator_id: creator.map(|creator| creator.id.clone()),
+            display_name: creator.map(|creator| creator.display_name.clone()),
+            starts_at: slot.starts_at,
+            ends_at: slot.ends_at,
+            is_live: creator.map_or(false, Creator::is_live),
+        }
+    }
+
+    /// Where the slot is at, worked out when asked as it changes with time alone
+    pub fn status(&self, now: OffsetDateTime) -> SlotStatus {
+        if now < self.starts_at {
+            SlotStatus::Upcoming
+        } else if now >= self.ends_at {
+            SlotStatus::Finished
+        } else if self.is_live {
+            SlotStatus::OnAir
+        } else if now - self.starts_at < LATE_GRACE_PERIOD {
+            SlotStatus::Late
+        } else {
+            SlotStatus::Missing
+        }
+    }
+}
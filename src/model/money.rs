@@ -0,0 +1,232 @@
+use std::fmt::{self, Display};
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{de, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+/// An ISO 4217 currency code, such as `USD`
+#[aliri_braid::braid]
+pub struct CurrencyCode;
+
+#[derive(Debug, Error)]
+#[error("{0:?} is not an ISO 4217 currency code")]
+pub struct InvalidCurrencyCode(String);
+
+impl CurrencyCode {
+    /// Accept only the shape of a currency code, three uppercase letters
+    pub fn parse(code: String) -> Result<Self, InvalidCurrencyCode> {
+        if code.len() == 3 && code.bytes().all(|byte| byte.is_ascii_uppercase()) {
+            Ok(Self::new(code))
+        } else {
+            Err(InvalidCurrencyCode(code))
+        }
+    }
+}
+
+impl Serialize for CurrencyCode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CurrencyCode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        CurrencyCode::parse(String::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+impl CurrencyCodeRef {
+    /// Digits after the decimal point in amounts of this currency
+    pub fn minor_units(&self) -> u32 {
+        match self.as_str() {
+            "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF"
+            | "UGX" | "UYI" | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+            "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+            "CLF" | "UYW" => 4,
+            _ => 2,
+        }
+    }
+
+    /// The symbol to prefix amounts in this currency with, if it is well known
+    pub fn symbol(&self) -> Option<&'static str> {
+        match self.as_str() {
+            "USD" => Some("$"),
+            "CAD" => Some("CA$"),
+            "AUD" => Some("A$"),
+            "NZD" => Some("NZ$"),
+            "EUR" => Some("€"),
+            "GBP" => Some("£"),
+            "JPY" => Some("¥"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("cannot combine an amount in {left} with an amount in {right}")]
+pub struct CurrencyMismatch {
+    pub left: CurrencyCode,
+    pub right: CurrencyCode,
+}
+
+/// An exact amount of money in a specific currency
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Money {
+    pub amount: Decimal,
+    pub currency: CurrencyCode,
+}
+
+impl Money {
+    pub fn new(amount: Decimal, currency: CurrencyCode) -> Self {
+        Self { amount, currency }
+    }
+
+    pub fn zero(currency: CurrencyCode) -> Self {
+        Self::new(Decimal::ZERO, currency)
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Result<Money, CurrencyMismatch> {
+        if self.currency != other.currency {
+            return Err(CurrencyMismatch {
+                left: self.currency.clone(),
+                right: other.currency.clone(),
+            });
+        }
+
+        Ok(Money::new(
+            self.amount + other.amount,
+            self.currency.clone(),
+        ))
+    }
+
+    /// Sum all of the amounts, which must all be in the given currency
+    pub fn try_sum<'m>(
+        currency: CurrencyCode,
+        amounts: impl IntoIterator<Item = &'m Money>,
+    ) -> Result<Money, CurrencyMismatch> {
+        amounts
+            .into_iter()
+            .try_fold(Money::zero(currency), |total, amount| {
+                total.checked_add(amount)
+            })
+    }
+}
+
+/// Formats as a human readable amount in the minor units of the currency, such as
+/// `$1,234.50`, `¥1,235` or `1,234.50 CHF`
+impl Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let minor_units = self.currency.minor_units();
+        let amount = self
+            .amount
+            .round_dp_with_strategy(minor_units, RoundingStrategy::MidpointAwayFromZero);
+
+        if amount.is_sign_negative() && !amount.is_zero() {
+            write!(f, "-")?;
+        }
+
+        if let Some(symbol) = self.currency.symbol() {
+            write!(f, "{symbol}")?;
+        }
+
+        let digits = format!("{:.*}", minor_units as usize, amount.abs());
+        let (whole, fraction) = digits.split_once('.').unwrap_or((&digits, ""));
+
+        // Group the whole part into thousands
+        for (index, digit) in whole.chars().enumerate() {
+            if index != 0 && (whole.len() - index) % 3 == 0 {
+                write!(f, ",")?;
+            }
+
+            write!(f, "{digit}")?;
+        }
+
+        if !fraction.is_empty() {
+            write!(f, ".{fraction}")?;
+        }
+
+        if self.currency.symbol().is_none() {
+            write!(f, " {}", self.currency)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut money = serializer.serialize_struct("Money", 3)?;
+        // Serialized as a string to avoid any loss of precision
+        money.serialize_field("amount", &self.amount.to_string())?;
+        money.serialize_field("currency", &self.currency)?;
+        money.serialize_field("formatted", &self.to_string())?;
+        money.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        /// The formatted amount is derived, so it is ignored
+        #[derive(Deserialize)]
+        struct SerializedMoney {
+            #[serde(with = "rust_decimal::serde::str")]
+            amount: Decimal,
+            currency: CurrencyCode,
+        }
+
+        let SerializedMoney { amount, currency } = SerializedMoney::deserialize(deserializer)?;
+
+        Ok(Money { amount, currency })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(amount: &str, currency: &'static str) -> Money {
+        Money::new(
+            Decimal::from_str_exact(amount).expect("test amount should be a valid decimal"),
+            CurrencyCode::from_static(currency),
+        )
+    }
+
+    #[test]
+    fn formats_in_minor_units() {
+        assert_eq!(money("1234.5", "USD").to_string(), "$1,234.50");
+        assert_eq!(money("1234.5", "JPY").to_string(), "¥1,235");
+        assert_eq!(money("-0.0005", "KWD").to_string(), "-0.001 KWD");
+        assert_eq!(money("999", "CHF").to_string(), "999.00 CHF");
+    }
+
+    #[test]
+    fn rejects_invalid_currency_codes() {
+        let parse = |json| serde_json::from_str::<Money>(json);
+
+        assert!(parse(r#"{"amount":"1.10","currency":"USD"}"#).is_ok());
+        assert!(parse(r#"{"amount":"1.10","currency":"usd"}"#).is_err());
+        assert!(parse(r#"{"amount":"1.10","currency":"DOLLARS"}"#).is_err());
+    }
+
+    #[test]
+    fn keeps_amounts_exact() {
+        let parsed: Money =
+            serde_json::from_str(r#"{"amount":"0.30000000000000000001","currency":"USD"}"#)
+                .expect("exact amount should deserialize");
+
+        assert_eq!(parsed, money("0.30000000000000000001", "USD"));
+        assert!(serde_json::from_str::<Money>(r#"{"amount":0.1,"currency":"USD"}"#).is_err());
+    }
+}
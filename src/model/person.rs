@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::PersonConfig;
+
+use super::{Creator, CreatorKey};
+
+/// The person or group behind one or more creator accounts
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Person {
+    pub name: String,
+    pub accounts: Vec<CreatorKey>,
+    pub live: bool,
+}
+
+impl Person {
+    /// Group the creators into people, creators not belonging to a configured person become
+    /// a person of their own. A creator listed under several people only belongs to the
+    /// first. Live people are sorted first
+    pub fn group(people: &[PersonConfig], creators: &[Creator]) -> Vec<Person> {
+        let mut grouped = Vec::with_capacity(creators.len());
+        let mut claimed = vec![false; creators.len()];
+
+        for person in people {
+            let indices = creators
+                .iter()
+                .enumerate()
+                .filter(|(index, creator)| {
+                    !claimed[*index]
+                        && person
+                            .accounts
+                            .iter()
+                            .any(|handle| handle.matches(creator))
+                })
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>();
+
+            let accounts = indices
+                .into_iter()
+                .map(|index| {
+                    claimed[index] = true;
+
+                    &creators[index]
+                })
+                .collect::<Vec<_>>();
+
+            if accounts.is_empty() {
+                continue;
+            }
+
+            grouped.push(Person::new(person.name.to_owned(), &accounts));
+        }
+
+        for (creator, _) in creators
+            .iter()
+            .zip(claimed)
+            .filter(|(_, claimed)| !claimed)
+        {
+            grouped.push(Person::new(creator.display_name.clone(), &[creator]));
+        }
+
+        grouped.sort_by(|a, b| b.live.cmp(&a.live).then_with(|| a.name.cmp(&b.name)));
+
+        grouped
+    }
+
+    fn new(name: String, accounts: &[&Creator]) -> Self {
+        Self {
+            name,
+            live: accounts.iter().any(|creator| creator.is_live()),
+            accounts: accounts
+                .iter()
+                .map(|creator| creator.key())
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use tokio::sync::{broadcast, watch};
+use tracing::{debug, error, warn};
+
+use crate::{
+    config::{AnnouncementTemplates, Config},
+    events::{Change, ChangeEvent},
+    model::{money::Money, Creator, LiveStreamDetails},
+    store::{announcements, Store},
+    watcher::{WatcherData, WatcherDataReceive},
+};
+
+pub mod bluesky;
+pub mod discord;
+pub mod mastodon;
+pub mod web_push;
+pub mod webhooks;
+
+/// Something worth telling the world about, with everything needed to word it
+#[derive(Debug, Clone)]
+pub enum Announcement {
+    Live {
+        creator: Creator,
+        stream: LiveStreamDetails,
+        campaign_name: String,
+        total: Money,
+    },
+    Milestone {
+        milestone: Money,
+        campaign_name: String,
+        total: Money,
+    },
+    GoalReached {
+        goal: Money,
+        campaign_name: String,
+        total: Money,
+    },
+}
+
+impl Announcement {
+    /// What to announce for a change, if anything
+    pub fn from_change(change: &Change, data: &WatcherData, config: &Config) -> Option<Self> {
+        let campaign_name = data.tiltify.name.clone();
+        let total = data.totals.total.clone();
+
+        Some(match change {
+            Change::WentLive { creator, stream } => {
+                // Other streams are none of our business
+                if !stream.campaign {
+                    return None;
+                }
+
+                let creator = creator.resolve(&data.creators)?;
+
+                if config
+                    .announcement_opt_out
+                    .iter()
+                    .any(|handle| handle.matches(creator))
+                {
+                    debug!(creator = %creator.key(), "creator opted out of announcements");
+                    return None;
+                }
+
+                Announcement::Live {
+                    creator: creator.clone(),
+                    stream: stream.clone(),
+                    campaign_name,
+                    total,
+                }
+            }
+            Change::MilestoneReached { milestone, .. } => Announcement::Milestone {
+                milestone: milestone.clone(),
+                campaign_name,
+                total,
+            },
+            Change::GoalReached { goal, .. } => Announcement::GoalReached {
+                goal: goal.clone(),
+                campaign_name,
+                total,
+            },
+            _ => return None,
+        })
+    }
+
+    /// Identifies the announcement, so it is only ever made once
+    pub fn key(&self) -> String {
+        match self {
+            Announcement::Live {
+                creator, stream, ..
+            } => format!(
+                "live:{}:{}",
+                creator.key(),
+                stream.start_time.unix_timestamp()
+            ),
+            Announcement::Milestone { milestone, .. } => {
+                format!("milestone:{}:{}", milestone.currency, milestone.amount)
+            }
+            Announcement::GoalReached { goal, .. } => {
+                format!("goal:{}:{}", goal.currency, goal.amount)
+            }
+        }
+    }
+
+    /// The page the announcement is about, if there is one
+    pub fn link(&self) -> Option<&str> {
+        match self {
+            Announcement::Live { stream, .. } => Some(&stream.href),
+            Announcement::Milestone { .. } | Announcement::GoalReached { .. } => None,
+        }
+    }
+
+    /// Word the announcement with the templates, shortening the stream title to fit within
+    /// the length limit if needed
+    pub fn render(&self, templates: &AnnouncementTemplates, length: PostLength) -> String {
+        let link = self.link();
+        let text = self.render_with_title(templates, None);
+        let overflow = length.of(&text, link).saturating_sub(length.max_chars);
+
+        if overflow == 0 {
+            return text;
+        }
+
+        let text = match self {
+            Announcement::Live { stream, .. } => {
+                // Room for the ellipsis
+                let title_chars = stream.title.chars().count().saturating_sub(overflow + 1);
+                let title = stream
+                    .title
+                    .chars()
+                    .take(title_chars)
+                    .chain(['…'])
+                    .collect::<String>();
+
+                self.render_with_title(templates, Some(&title))
+            }
+            Announcement::Milestone { .. } | Announcement::GoalReached { .. } => text,
+        };
+
+        length.truncate(&text, link)
+    }
+
+    fn render_with_title(&self, templates: &AnnouncementTemplates, title: Option<&str>) -> String {
+        match self {
+            Announcement::Live {
+                creator,
+                stream,
+                campaign_name,
+                total,
+            } => fill(
+                templates.live,
+                &[
+                    ("name", creator.display_name.as_str()),
+                    ("title", title.unwrap_or(&stream.title)),
+                    ("category", stream.category.as_deref().unwrap_or_default()),
+                    ("link", stream.href.as_str()),
+                    ("campaign", campaign_name.as_str()),
+                    ("total", total.to_string().as_str()),
+                ],
+            ),
+            Announcement::Milestone {
+                milestone,
+                campaign_name,
+                total,
+            } => fill(
+                templates.milestone,
+                &[
+                    ("milestone", milestone.to_string().as_str()),
+                    ("campaign", campaign_name.as_str()),
+                    ("total", total.to_string().as_str()),
+                ],
+            ),
+            Announcement::GoalReached {
+                goal,
+                campaign_name,
+                total,
+            } => fill(
+                templates.goal_reached,
+                &[
+                    ("goal", goal.to_string().as_str()),
+                    ("campaign", campaign_name.as_str()),
+                    ("total", total.to_string().as_str()),
+                ],
+            ),
+        }
+    }
+}
+
+/// How a service counts the length of a post
+#[derive(Debug, Clone, Copy)]
+pub struct PostLength {
+    pub max_chars: usize,
+    /// Links count as this many characters however long they are
+    pub link_chars: Option<usize>,
+}
+
+impl PostLength {
+    /// The length of a text as the service counts it
+    fn of(&self, text: &str, link: Option<&str>) -> usize {
+        let mut length = 0;
+        let mut rest = text;
+
+        while let Some((piece, piece_length)) = self.next_piece(rest, link) {
+            length += piece_length;
+            rest = &rest[piece.len()..];
+        }
+
+        length
+    }
+
+    /// Cut a text down to the limit. The link is kept whole or left out, as a cut link would
+    /// lead nowhere
+    fn truncate(&self, text: &str, link: Option<&str>) -> String {
+        let mut truncated = String::with_capacity(text.len());
+        let mut length = 0;
+        let mut rest = text;
+
+        while let Some((piece, piece_length)) = self.next_piece(rest, link) {
+            if length + piece_length > self.max_chars {
+                break;
+            }
+
+            truncated.push_str(piece);
+            length += piece_length;
+            rest = &rest[piece.len()..];
+        }
+
+        truncated
+    }
+
+    /// The link if the text starts with it, otherwise the next character, with its length
+    fn next_piece<'t>(&self, text: &'t str, link: Option<&str>) -> Option<(&'t str, usize)> {
+        if let Some(link) = link.filter(|link| !link.is_empty() && text.starts_with(link)) {
+            let length = self.link_chars.unwrap_or_else(|| link.chars().count());
+
+            return Some((&text[..link.len()], length));
+        }
+
+        let next = text.chars().next()?;
+
+        Some((&text[..next.len_utf8()], 1))
+    }
+}
+
+/// Replace the `{placeholders}` of a template in a single pass, so values containing braces
+/// are left alone. Unknown placeholders are kept as they are
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let placeholder = rest.find('}').and_then(|end| {
+            let name = &rest[1..end];
+            let (_, value) = values
+                .iter()
+                .find(|(placeholder, _)| *placeholder == name)?;
+
+            Some((end, value))
+        });
+
+        match placeholder {
+            Some((end, value)) => {
+                text.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                text.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    text.push_str(rest);
+    text
+}
+
+/// The announcements for one notification channel, each only ever handed out once,
+/// even across restarts
+pub struct Announcements<'c> {
+    /// Name of the channel, announcements are tracked separately for each
+    channel: &'static str,
+    config: &'c Config,
+    store: Store,
+    events: broadcast::Receiver<ChangeEvent>,
+    watcher_data: watch::Receiver<WatcherDataReceive>,
+}
+
+impl<'c> Announcements<'c> {
+    pub fn new(
+        channel: &'static str,
+        config: &'c Config,
+        store: Store,
+        events: broadcast::Receiver<ChangeEvent>,
+        watcher_data: watch::Receiver<WatcherDataReceive>,
+    ) -> Self {
+        Self {
+            channel,
+            config,
+            store,
+            events,
+            watcher_data,
+        }
+    }
+
+    /// Wait for the next announcement that has not been made yet, `None` once the
+    /// events stop
+    pub async fn next(&mut self) -> Option<Announcement> {
+        loop {
+            let announcement = self.next_unclaimed().await?;
+
+            if claim(&self.store, self.channel, &announcement).await {
+                return Some(announcement);
+            }
+        }
+    }
+
+    /// Wait for the next announcement without claiming it, for channels that keep track of
+    /// their claims themselves. `None` once the events stop
+    pub async fn next_unclaimed(&mut self) -> Option<Announcement> {
+        loop {
+            let event = match self.events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(
+                        channel = self.channel,
+                        skipped, "announcements fell behind, events were dropped"
+                    );
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            };
+
+            let announcement = match self.watcher_data.borrow().as_deref() {
+                Some(data) => Announcement::from_change(&event.change, data, self.config),
+                None => None,
+            };
+
+            if let Some(announcement) = announcement {
+                return Some(announcement);
+            }
+        }
+    }
+
+    /// Forget about a failed announcement, so it can be made again
+    pub async fn failed(&self, announcement: &Announcement) {
+        release(&self.store, self.channel, announcement).await;
+    }
+}
+
+/// Claim an announcement for a channel, false if it was already made
+pub async fn claim(store: &Store, channel: &str, announcement: &Announcement) -> bool {
+    let key = announcement.key();
+    let claimed = {
+        let channel = channel.to_owned();
+        let key = key.clone();
+
+        store
+            .with_connection(move |connection| announcements::claim(connection, &channel, &key))
+            .await
+    };
+
+    match claimed {
+        Ok(claimed) => {
+            if !claimed {
+                debug!(channel, %key, "already announced");
+            }
+
+            claimed
+        }
+        Err(error) => {
+            // Better to risk a duplicate than to miss an announcement
+            error!(
+                ?error,
+                channel, "failed to check for a previous announcement"
+            );
+            true
+        }
+    }
+}
+
+/// Give up the claim on an announcement that could not be made, so it can be made again
+pub async fn release(store: &Store, channel: &str, announcement: &Announcement) {
+    let key = announcement.key();
+    let result = {
+        let channel = channel.to_owned();
+
+        store
+            .with_connection(move |connection| announcements::release(connection, &channel, &key))
+            .await
+    };
+
+    if let Err(error) = result {
+        error!(?error, channel, "failed to release a failed announcement");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PostLength;
+
+    const LINK: &str = "https://twitch.tv/creator";
+
+    #[test]
+    fn never_cuts_inside_the_link() {
+        let length = PostLength {
+            max_chars: 40,
+            link_chars: None,
+        };
+        let text = format!("Live now, come say hi {LINK}");
+
+        assert_eq!(length.truncate(&text, Some(LINK)), "Live now, come say hi ");
+    }
+
+    #[test]
+    fn counts_links_as_fixed_length() {
+        let length = PostLength {
+            max_chars: 30,
+            link_chars: Some(5),
+        };
+        let text = format!("Live now, come say hi {LINK}");
+
+        assert_eq!(length.of(&text, Some(LINK)), 27);
+        assert_eq!(length.truncate(&text, Some(LINK)), text);
+    }
+}
@@ -0,0 +1,310 @@
+use std::fmt::Debug;
+
+use color_eyre::eyre::Context;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::sync::{broadcast, watch};
+use tracing::{debug, error, info, warn};
+
+use crate::{config::Config, events::ChangeEvent, store::Store, watcher::WatcherDataReceive};
+
+use super::{Announcement, Announcements, PostLength};
+
+/// The limit is 300 graphemes, counting characters is close enough
+const LENGTH: PostLength = PostLength {
+    max_chars: 300,
+    link_chars: None,
+};
+
+#[aliri_braid::braid(serde, display = "omit", debug = "omit")]
+pub struct BlueskyAppPassword;
+
+impl Debug for BlueskyAppPassword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "*****")
+    }
+}
+
+fn default_service_url() -> Url {
+    Url::parse("https://bsky.social").expect("url should be valid")
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BlueskyEnvironment {
+    /// Handle or DID of the account, such as `creatorsforacause.bsky.social`
+    #[serde(rename = "bluesky_identifier")]
+    identifier: String,
+    /// An app password, never the account's own password
+    #[serde(rename = "bluesky_app_password")]
+    app_password: BlueskyAppPassword,
+    /// The personal data server hosting the account
+    #[serde(rename = "bluesky_service_url", default = "default_service_url")]
+    service_url: Url,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateSession<'s> {
+    identifier: &'s str,
+    password: &'s str,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Session {
+    access_jwt: String,
+    did: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadedBlob {
+    /// Passed back as is when referencing the blob
+    blob: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateRecord<'r> {
+    repo: &'r str,
+    collection: &'static str,
+    record: Post<'r>,
+}
+
+#[derive(Debug, Serialize)]
+struct Post<'p> {
+    #[serde(rename = "$type")]
+    r#type: &'static str,
+    text: &'p str,
+    #[serde(rename = "createdAt", with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    facets: Vec<Facet<'p>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embed: Option<ExternalEmbed<'p>>,
+}
+
+/// Links are not detected in the text, they have to be marked up as facets
+#[derive(Debug, Serialize)]
+struct Facet<'f> {
+    index: ByteSlice,
+    features: [LinkFeature<'f>; 1],
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ByteSlice {
+    byte_start: usize,
+    byte_end: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct LinkFeature<'f> {
+    #[serde(rename = "$type")]
+    r#type: &'static str,
+    uri: &'f str,
+}
+
+#[derive(Debug, Serialize)]
+struct ExternalEmbed<'e> {
+    #[serde(rename = "$type")]
+    r#type: &'static str,
+    external: LinkCard<'e>,
+}
+
+/// Unlike mastodon, the link card is built by the poster
+#[derive(Debug, Serialize)]
+struct LinkCard<'c> {
+    uri: &'c str,
+    title: &'c str,
+    description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumb: Option<serde_json::Value>,
+}
+
+struct BlueskyClient {
+    http_client: reqwest::Client,
+    environment: BlueskyEnvironment,
+}
+
+/// Post go live and milestone announcements to bluesky, or only log them if dry running
+#[tracing::instrument(skip_all)]
+pub async fn bluesky_notifier(
+    http_client: reqwest::Client,
+    environment: BlueskyEnvironment,
+    config: &Config,
+    store: Store,
+    events: broadcast::Receiver<ChangeEvent>,
+    watcher_data: watch::Receiver<WatcherDataReceive>,
+    dry_run: bool,
+) {
+    info!(identifier = %environment.identifier, dry_run, "announcing to bluesky");
+
+    let client = BlueskyClient {
+        http_client,
+        environment,
+    };
+
+    // Dry runs are tracked separately, so they never stop the real announcement
+    let channel = if dry_run { "bluesky dry run" } else { "bluesky" };
+    let mut announcements = Announcements::new(channel, config, store, events, watcher_data);
+
+    while let Some(announcement) = announcements.next().await {
+        let text = announcement.render(&config.social.bluesky, LENGTH);
+
+        if dry_run {
+            info!(%text, card = ?announcement.link(), "would post to bluesky");
+            continue;
+        }
+
+        match client.post(&announcement, &text).await {
+            Ok(()) => debug!(key = %announcement.key(), "announced on bluesky"),
+            Err(error) => {
+                error!(?error, "failed to post to bluesky");
+                announcements.failed(&announcement).await;
+            }
+        }
+    }
+}
+
+impl BlueskyClient {
+    fn xrpc_url(&self, method: &str) -> Url {
+        self.environment
+            .service_url
+            .join(&format!("xrpc/{method}"))
+            .expect("xrpc url should be valid")
+    }
+
+    async fn post(&self, announcement: &Announcement, text: &str) -> color_eyre::Result<()> {
+        // Announcements are rare, so a fresh session is simpler than refreshing one
+        let session: Session = self
+            .http_client
+            .post(self.xrpc_url("com.atproto.server.createSession"))
+            .json(&CreateSession {
+                identifier: &self.environment.identifier,
+                password: self.environment.app_password.as_str(),
+            })
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .wrap_err("failed to create session")?
+            .json()
+            .await
+            .wrap_err("incompatible session")?;
+
+        let facets = announcement
+            .link()
+            .and_then(|link| {
+                let byte_start = text.find(link)?;
+
+                Some(Facet {
+                    index: ByteSlice {
+                        byte_start,
+                        byte_end: byte_start + link.len(),
+                    },
+                    features: [LinkFeature {
+                        r#type: "app.bsky.richtext.facet#link",
+                        uri: link,
+                    }],
+                })
+            })
+            .into_iter()
+            .collect();
+
+        let embed = match announcement {
+            Announcement::Live {
+                creator,
+                stream,
+                campaign_name,
+                ..
+            } => {
+                let thumbnail_url = stream.thumbnail_url.as_ref().unwrap_or(&creator.icon_url);
+
+                Some(ExternalEmbed {
+                    r#type: "app.bsky.embed.external",
+                    external: LinkCard {
+                        uri: &stream.href,
+                        title: &stream.title,
+                        description: format!(
+                            "{} is streaming for {campaign_name}",
+                            creator.display_name
+                        ),
+                        thumb: self.upload_thumbnail(&session, thumbnail_url).await,
+                    },
+                })
+            }
+            Announcement::Milestone { .. } | Announcement::GoalReached { .. } => None,
+        };
+
+        self.http_client
+            .post(self.xrpc_url("com.atproto.repo.createRecord"))
+            .bearer_auth(&session.access_jwt)
+            .json(&CreateRecord {
+                repo: &session.did,
+                collection: "app.bsky.feed.post",
+                record: Post {
+                    r#type: "app.bsky.feed.post",
+                    text,
+                    created_at: OffsetDateTime::now_utc(),
+                    facets,
+                    embed,
+                },
+            })
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .wrap_err("failed to create post")?;
+
+        Ok(())
+    }
+
+    /// Upload the card's thumbnail, a card without one is better than no post at all
+    async fn upload_thumbnail(
+        &self,
+        session: &Session,
+        thumbnail_url: &str,
+    ) -> Option<serde_json::Value> {
+        let result = async {
+            let thumbnail = self
+                .http_client
+                .get(thumbnail_url)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .wrap_err("failed to download thumbnail")?;
+
+            let content_type = thumbnail
+                .headers()
+                .get("content-type")
+                .cloned()
+                .unwrap_or_else(|| "image/jpeg".parse().expect("header should be valid"));
+
+            let thumbnail = thumbnail
+                .bytes()
+                .await
+                .wrap_err("failed to read thumbnail")?;
+
+            let UploadedBlob { blob } = self
+                .http_client
+                .post(self.xrpc_url("com.atproto.repo.uploadBlob"))
+                .bearer_auth(&session.access_jwt)
+                .header("content-type", content_type)
+                .body(thumbnail)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .wrap_err("failed to upload thumbnail")?
+                .json()
+                .await
+                .wrap_err("incompatible upload response")?;
+
+            color_eyre::Result::<_>::Ok(blob)
+        };
+
+        match result.await {
+            Ok(blob) => Some(blob),
+            Err(error) => {
+                warn!(?error, "posting link card without a thumbnail");
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,572 @@
+use std::time::Duration;
+
+use color_eyre::eyre::{bail, Context};
+use futures::{stream::FuturesUnordered, StreamExt};
+use hyper::StatusCode;
+use reqwest::{Response, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use tokio::sync::{broadcast, watch};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    config::Config,
+    events::ChangeEvent,
+    model::StreamingService,
+    store::Store,
+    watcher::WatcherDataReceive,
+};
+
+use super::{claim, release, Announcement, Announcements};
+
+/// Attempts at delivering a message before giving up, if discord keeps rate limiting
+const MAX_ATTEMPTS: u32 = 5;
+/// Longest wait for a rate limit to reset, anything longer is treated as a failure
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+const TWITCH_COLOR: u32 = 0x9146ff;
+const YOUTUBE_COLOR: u32 = 0xff0000;
+const CAMPAIGN_COLOR: u32 = 0x008000;
+
+#[derive(Deserialize, Debug)]
+pub struct DiscordEnvironment {
+    /// Comma separated discord webhook urls to post announcements to
+    #[serde(rename = "discord_webhook_urls")]
+    webhook_urls: String,
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookMessage {
+    embeds: [Embed; 1],
+    allowed_mentions: AllowedMentions,
+}
+
+/// Nobody should be pinged by a title
+#[derive(Debug, Serialize)]
+struct AllowedMentions {
+    parse: [&'static str; 0],
+}
+
+#[derive(Debug, Serialize)]
+struct Embed {
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    color: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail: Option<EmbedImage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<EmbedImage>,
+    fields: Vec<EmbedField>,
+    #[serde(with = "time::serde::rfc3339")]
+    timestamp: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbedImage {
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbedField {
+    name: &'static str,
+    value: String,
+    inline: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct RateLimited {
+    /// Seconds to wait before trying again
+    retry_after: f64,
+}
+
+impl From<&Announcement> for WebhookMessage {
+    fn from(announcement: &Announcement) -> Self {
+        let embed = match announcement {
+            Announcement::Live {
+                creator,
+                stream,
+                campaign_name,
+                total,
+            } => Embed {
+                title: format!("{} is now live for {campaign_name}", creator.display_name),
+                url: Some(stream.href.clone()),
+                description: Some(stream.title.clone()),
+                color: match creator.service {
+                    StreamingService::Twitch => TWITCH_COLOR,
+                    StreamingService::Youtube => YOUTUBE_COLOR,
+                },
+                thumbnail: Some(EmbedImage {
+                    url: creator.icon_url.clone(),
+                }),
+                image: stream
+                    .thumbnail_url
+                    .clone()
+                    .map(|url| EmbedImage { url }),
+                fields: stream
+                    .category
+                    .iter()
+                    .map(|category| EmbedField {
+                        name: "Streaming",
+                        value: category.clone(),
+                        inline: true,
+                    })
+                    .chain([EmbedField {
+                        name: "Raised so far",
+                        value: total.to_string(),
+                        inline: true,
+                    }])
+                    .collect(),
+                timestamp: stream.start_time,
+            },
+            Announcement::Milestone {
+                milestone,
+                campaign_name,
+                total,
+            } => Embed {
+                title: format!("{campaign_name} has raised over {milestone}!"),
+                url: None,
+                description: Some(format!(
+                    "Thank you to everyone who has donated so far, {total} and counting"
+                )),
+                color: CAMPAIGN_COLOR,
+                thumbnail: None,
+                image: None,
+                fields: Vec::new(),
+                timestamp: OffsetDateTime::now_utc(),
+            },
+            Announcement::GoalReached {
+                goal,
+                campaign_name,
+                total,
+            } => Embed {
+                title: format!("{campaign_name} has reached its goal of {goal}!"),
+                url: None,
+                description: Some(format!(
+                    "Thank you to everyone who made it happen, {total} raised so far"
+                )),
+                color: CAMPAIGN_COLOR,
+                thumbnail: None,
+                image: None,
+                fields: Vec::new(),
+                timestamp: OffsetDateTime::now_utc(),
+            },
+        };
+
+        WebhookMessage {
+            embeds: [embed],
+            allowed_mentions: AllowedMentions { parse: [] },
+        }
+    }
+}
+
+/// A webhook, announcements are tracked for each one separately, so one that failed can be
+/// announced again without repeating it on the others
+struct Webhook {
+    url: Url,
+    /// Identifies the webhook's announcements, without storing the secret url
+    channel: String,
+}
+
+impl Webhook {
+    fn new(url: Url) -> Self {
+        let digest = hex::encode(Sha256::digest(url.as_str().as_bytes()));
+
+        Self {
+            channel: format!("discord:{}", &digest[..16]),
+            url,
+        }
+    }
+}
+
+/// Post go live and milestone announcements to discord
+#[tracing::instrument(skip_all)]
+pub async fn discord_notifier(
+    http_client: reqwest::Client,
+    environment: DiscordEnvironment,
+    config: &Config,
+    store: Store,
+    events: broadcast::Receiver<ChangeEvent>,
+    watcher_data: watch::Receiver<WatcherDataReceive>,
+) {
+    let webhooks = match environment
+        .webhook_urls
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(|url| Url::parse(url).map(Webhook::new))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(webhooks) => webhooks,
+        Err(error) => {
+            // The urls themselves are secret, so they are never logged
+            error!(%error, "invalid discord webhook url, not announcing to discord");
+            return;
+        }
+    };
+
+    info!(webhooks = webhooks.len(), "announcing to discord");
+
+    let mut announcements =
+        Announcements::new("discord", config, store.clone(), events, watcher_data);
+
+    while let Some(announcement) = announcements.next_unclaimed().await {
+        announce(&http_client, &store, &webhooks, &announcement).await;
+    }
+}
+
+/// Post an announcement to every webhook that has not had it yet, returning how many it
+/// was delivered to
+async fn announce(
+    http_client: &reqwest::Client,
+    store: &Store,
+    webhooks: &[Webhook],
+    announcement: &Announcement,
+) -> usize {
+    let message = WebhookMessage::from(announcement);
+
+    let delivered = webhooks
+        .iter()
+        .enumerate()
+        .map(|(index, webhook)| {
+            let message = &message;
+
+            async move {
+                if !claim(store, &webhook.channel, announcement).await {
+                    return false;
+                }
+
+                match execute_webhook(http_client, &webhook.url, message).await {
+                    Ok(()) => true,
+                    Err(error) => {
+                        error!(?error, webhook = index, "failed to post discord announcement");
+                        release(store, &webhook.channel, announcement).await;
+                        false
+                    }
+                }
+            }
+        })
+        .collect::<FuturesUnordered<_>>()
+        .fold(0, |delivered, success| async move { delivered + usize::from(success) })
+        .await;
+
+    debug!(key = %announcement.key(), delivered, "announced on discord");
+
+    delivered
+}
+
+/// Post a message to a webhook, waiting out any rate limits
+async fn execute_webhook(
+    http_client: &reqwest::Client,
+    url: &Url,
+    message: &WebhookMessage,
+) -> color_eyre::Result<()> {
+    for attempt in 1..=MAX_ATTEMPTS {
+        let response = http_client
+            .post(url.clone())
+            .json(message)
+            .send()
+            .await
+            // The url contains the webhook's token
+            .map_err(|error| error.without_url())
+            .wrap_err("failed to execute webhook")?;
+
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let RateLimited { retry_after } = response
+                .json()
+                .await
+                .map_err(|error| error.without_url())
+                .wrap_err("incompatible rate limit response")?;
+
+            if !(0.0..=MAX_RATE_LIMIT_WAIT.as_secs_f64()).contains(&retry_after) {
+                bail!("rate limited for too long ({retry_after}s)");
+            }
+
+            let retry_after = Duration::from_secs_f64(retry_after);
+
+            warn!(attempt, ?retry_after, "rate limited by discord");
+            tokio::time::sleep(retry_after).await;
+
+            continue;
+        }
+
+        let response = response
+            .error_for_status()
+            .map_err(|error| error.without_url())
+            .wrap_err("webhook returned a non-success status")?;
+
+        // Wait out an exhausted bucket, instead of running into the rate limit next time
+        if let Some(reset_after) = exhausted_bucket_reset(&response) {
+            debug!(?reset_after, "discord rate limit bucket exhausted");
+            tokio::time::sleep(reset_after).await;
+        }
+
+        return Ok(());
+    }
+
+    bail!("still rate limited after {MAX_ATTEMPTS} attempts")
+}
+
+/// The time until the rate limit resets, if no requests are remaining
+fn exhausted_bucket_reset(response: &Response) -> Option<Duration> {
+    let headers = response.headers();
+
+    if headers.get("x-ratelimit-remaining")? != "0" {
+        return None;
+    }
+
+    let reset_after: f64 = headers
+        .get("x-ratelimit-reset-after")?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()?;
+
+    (0.0..=MAX_RATE_LIMIT_WAIT.as_secs_f64())
+        .contains(&reset_after)
+        .then(|| Duration::from_secs_f64(reset_after))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, VecDeque},
+        net::SocketAddr,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+        time::Instant,
+    };
+
+    use axum::{
+        extract::{Path, State},
+        http::HeaderMap,
+        routing::post,
+        Router, Server,
+    };
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::model::money::{CurrencyCode, Money};
+
+    /// A response the stand-in receiver gives, instead of the usual 204
+    struct Scripted {
+        status: StatusCode,
+        headers: HeaderMap,
+        body: &'static str,
+    }
+
+    impl Scripted {
+        fn new(status: StatusCode, body: &'static str) -> Self {
+            Self {
+                status,
+                headers: HeaderMap::new(),
+                body,
+            }
+        }
+
+        fn header(mut self, name: &'static str, value: &'static str) -> Self {
+            self.headers.insert(
+                name,
+                value.parse().expect("scripted header should be valid"),
+            );
+            self
+        }
+    }
+
+    /// A local stand-in for discord's webhook endpoint
+    #[derive(Clone, Default)]
+    struct Receiver {
+        scripted: Arc<Mutex<HashMap<String, VecDeque<Scripted>>>>,
+        received: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Receiver {
+        async fn start() -> (Self, SocketAddr) {
+            let receiver = Self::default();
+
+            let app = Router::new()
+                .route("/webhooks/:name", post(Self::handler))
+                .with_state(receiver.clone());
+
+            let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+                .serve(app.into_make_service());
+            let address = server.local_addr();
+            tokio::spawn(server);
+
+            (receiver, address)
+        }
+
+        async fn handler(
+            State(receiver): State<Self>,
+            Path(name): Path<String>,
+        ) -> (StatusCode, HeaderMap, &'static str) {
+            receiver
+                .received
+                .lock()
+                .expect("receiver lock should not be poisoned")
+                .push(name.clone());
+
+            let scripted = receiver
+                .scripted
+                .lock()
+                .expect("receiver lock should not be poisoned")
+                .get_mut(&name)
+                .and_then(VecDeque::pop_front)
+                .unwrap_or_else(|| Scripted::new(StatusCode::NO_CONTENT, ""));
+
+            (scripted.status, scripted.headers, scripted.body)
+        }
+
+        fn script(&self, name: &str, responses: impl IntoIterator<Item = Scripted>) {
+            self.scripted
+                .lock()
+                .expect("receiver lock should not be poisoned")
+                .entry(name.to_owned())
+                .or_default()
+                .extend(responses);
+        }
+
+        fn received(&self, name: &str) -> usize {
+            self.received
+                .lock()
+                .expect("receiver lock should not be poisoned")
+                .iter()
+                .filter(|received| *received == name)
+                .count()
+        }
+    }
+
+    fn webhook(address: SocketAddr, name: &str) -> Webhook {
+        let url = format!("http://{address}/webhooks/{name}");
+
+        Webhook::new(Url::parse(&url).expect("webhook url should be valid"))
+    }
+
+    fn announcement() -> Announcement {
+        let money = |amount| Money::new(Decimal::new(amount, 0), CurrencyCode::from_static("USD"));
+
+        Announcement::Milestone {
+            milestone: money(1_000),
+            campaign_name: "Creators for a Cause".to_owned(),
+            total: money(1_234),
+        }
+    }
+
+    /// A database that outlives the store, to restart with
+    fn database_path(test: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "creatorsforacause-discord-{test}-{}.sqlite",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        path
+    }
+
+    #[tokio::test]
+    async fn waits_out_rate_limit() {
+        let (receiver, address) = Receiver::start().await;
+        receiver.script(
+            "limited",
+            [Scripted::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                r#"{"message": "You are being rate limited.", "retry_after": 0.2, "global": false}"#,
+            )
+            .header("content-type", "application/json")],
+        );
+
+        let start = Instant::now();
+        let result = execute_webhook(
+            &reqwest::Client::new(),
+            &webhook(address, "limited").url,
+            &WebhookMessage::from(&announcement()),
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(receiver.received("limited"), 2);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_long_rate_limit() {
+        let (receiver, address) = Receiver::start().await;
+        receiver.script(
+            "limited",
+            [Scripted::new(StatusCode::TOO_MANY_REQUESTS, r#"{"retry_after": 3600}"#)
+                .header("content-type", "application/json")],
+        );
+
+        let result = execute_webhook(
+            &reqwest::Client::new(),
+            &webhook(address, "limited").url,
+            &WebhookMessage::from(&announcement()),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(receiver.received("limited"), 1);
+    }
+
+    #[tokio::test]
+    async fn waits_out_exhausted_bucket() {
+        let (receiver, address) = Receiver::start().await;
+        receiver.script(
+            "exhausted",
+            [Scripted::new(StatusCode::NO_CONTENT, "")
+                .header("x-ratelimit-remaining", "0")
+                .header("x-ratelimit-reset-after", "0.2")],
+        );
+
+        let start = Instant::now();
+        let result = execute_webhook(
+            &reqwest::Client::new(),
+            &webhook(address, "exhausted").url,
+            &WebhookMessage::from(&announcement()),
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(receiver.received("exhausted"), 1);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn never_repeats_after_restart() {
+        let (receiver, address) = Receiver::start().await;
+        let http_client = reqwest::Client::new();
+        let webhooks = [webhook(address, "a")];
+        let path = database_path("restart");
+
+        let store = Store::open(&path).expect("store should open");
+        assert_eq!(announce(&http_client, &store, &webhooks, &announcement()).await, 1);
+        drop(store);
+
+        let store = Store::open(&path).expect("store should open");
+        assert_eq!(announce(&http_client, &store, &webhooks, &announcement()).await, 0);
+
+        assert_eq!(receiver.received("a"), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_only_failed_webhooks() {
+        let (receiver, address) = Receiver::start().await;
+        receiver.script(
+            "broken",
+            [Scripted::new(StatusCode::INTERNAL_SERVER_ERROR, "")],
+        );
+
+        let http_client = reqwest::Client::new();
+        let webhooks = [webhook(address, "working"), webhook(address, "broken")];
+        let store = Store::open(&database_path("partial")).expect("store should open");
+
+        assert_eq!(announce(&http_client, &store, &webhooks, &announcement()).await, 1);
+        assert_eq!(announce(&http_client, &store, &webhooks, &announcement()).await, 1);
+
+        assert_eq!(receiver.received("working"), 1);
+        assert_eq!(receiver.received("broken"), 2);
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt::Debug;
+
+use color_eyre::eyre::Context;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, watch};
+use tracing::{debug, error, info};
+
+use crate::{config::Config, events::ChangeEvent, store::Store, watcher::WatcherDataReceive};
+
+use super::{Announcement, Announcements, PostLength};
+
+/// The default character limit of a status, instances can raise it. Every link counts as 23
+/// characters, whatever its length
+const LENGTH: PostLength = PostLength {
+    max_chars: 500,
+    link_chars: Some(23),
+};
+
+#[aliri_braid::braid(serde, display = "omit", debug = "omit")]
+pub struct MastodonAccessToken;
+
+impl Debug for MastodonAccessToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "*****")
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MastodonEnvironment {
+    /// The instance the account is on, such as `https://mastodon.social`
+    #[serde(rename = "mastodon_instance_url")]
+    instance_url: Url,
+    /// Access token of an application with the `write:statuses` scope
+    #[serde(rename = "mastodon_access_token")]
+    access_token: MastodonAccessToken,
+}
+
+#[derive(Debug, Serialize)]
+struct NewStatus<'s> {
+    status: &'s str,
+    visibility: &'static str,
+}
+
+/// Post go live and milestone announcements as statuses on mastodon, or only log them if
+/// dry running
+#[tracing::instrument(skip_all)]
+pub async fn mastodon_notifier(
+    http_client: reqwest::Client,
+    environment: MastodonEnvironment,
+    config: &Config,
+    store: Store,
+    events: broadcast::Receiver<ChangeEvent>,
+    watcher_data: watch::Receiver<WatcherDataReceive>,
+    dry_run: bool,
+) {
+    let statuses_url = match environment.instance_url.join("api/v1/statuses") {
+        Ok(statuses_url) => statuses_url,
+        Err(error) => {
+            error!(%error, "invalid mastodon instance url, not announcing to mastodon");
+            return;
+        }
+    };
+
+    info!(instance = %environment.instance_url, dry_run, "announcing to mastodon");
+
+    // Dry runs are tracked separately, so they never stop the real announcement
+    let channel = if dry_run { "mastodon dry run" } else { "mastodon" };
+    let mut announcements = Announcements::new(channel, config, store, events, watcher_data);
+
+    while let Some(announcement) = announcements.next().await {
+        let status = announcement.render(&config.social.mastodon, LENGTH);
+
+        if dry_run {
+            info!(%status, "would post mastodon status");
+            continue;
+        }
+
+        let result = post_status(
+            &http_client,
+            &statuses_url,
+            &environment.access_token,
+            &announcement,
+            &status,
+        )
+        .await;
+
+        match result {
+            Ok(()) => debug!(key = %announcement.key(), "announced on mastodon"),
+            Err(error) => {
+                error!(?error, "failed to post mastodon status");
+                announcements.failed(&announcement).await;
+            }
+        }
+    }
+}
+
+async fn post_status(
+    http_client: &reqwest::Client,
+    statuses_url: &Url,
+    access_token: &MastodonAccessTokenRef,
+    announcement: &Announcement,
+    status: &str,
+) -> color_eyre::Result<()> {
+    http_client
+        .post(statuses_url.clone())
+        .bearer_auth(access_token.as_str())
+        // Mastodon ignores repeats of the same key for an hour, in case a response is lost
+        .header("idempotency-key", announcement.key())
+        // The instance generates the link card from the link in the status
+        .json(&NewStatus {
+            status,
+            visibility: "public",
+        })
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .wrap_err("failed to post status")?;
+
+    Ok(())
+}
@@ -0,0 +1,449 @@
+use std::{fmt::Debug, time::Duration};
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes128Gcm, Nonce,
+};
+use color_eyre::eyre::{bail, eyre, Context};
+use futures::StreamExt;
+use hkdf::Hkdf;
+use hyper::StatusCode;
+use p256::{
+    ecdsa::{signature::Signer, Signature, SigningKey},
+    elliptic_curve::sec1::ToEncodedPoint,
+    PublicKey, SecretKey,
+};
+use rand::{rngs::OsRng, RngCore};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use time::OffsetDateTime;
+use tokio::sync::{broadcast, watch};
+use tracing::{debug, error, info, trace, warn};
+
+use crate::{
+    config::Config,
+    events::ChangeEvent,
+    store::{
+        push::{self, PushSubscription},
+        Store,
+    },
+    watcher::WatcherDataReceive,
+};
+
+use super::{Announcement, Announcements};
+
+/// How long a push service should hold on to a message for an offline browser,
+/// a stream is old news after this
+const TTL: Duration = Duration::from_secs(60 * 60);
+/// Lifetime of the VAPID token, push services reject anything longer than a day
+const VAPID_TOKEN_LIFETIME: time::Duration = time::Duration::hours(12);
+/// Size of the single record a message is encrypted into
+const RECORD_SIZE: u32 = 4096;
+/// Push messages sent at once, a popular creator can have a lot of followers
+const MAX_CONCURRENT_SENDS: usize = 16;
+/// The push services of the browsers, the only hosts messages are ever sent to
+const PUSH_SERVICE_HOSTS: &[&str] = &[
+    // Chrome, Edge and most other Chromium based browsers
+    "fcm.googleapis.com",
+    // Firefox
+    "push.services.mozilla.com",
+    // Safari
+    "push.apple.com",
+    // Legacy Edge and Windows apps
+    "notify.windows.com",
+];
+
+#[aliri_braid::braid(serde, display = "omit", debug = "omit")]
+pub struct VapidPrivateKey;
+
+impl Debug for VapidPrivateKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "*****")
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct WebPushEnvironment {
+    /// Base64url encoded P-256 private key, as generated by most web push libraries
+    #[serde(rename = "vapid_private_key")]
+    private_key: VapidPrivateKey,
+    /// Contact for push services if something goes wrong, a `mailto:` or `https:` url
+    #[serde(rename = "vapid_subject")]
+    subject: String,
+}
+
+/// The application server's identity, used to sign every push message
+#[derive(Clone)]
+pub struct Vapid {
+    signing_key: SigningKey,
+    subject: String,
+}
+
+impl Vapid {
+    pub fn new(environment: WebPushEnvironment) -> color_eyre::Result<Self> {
+        let private_key = base64::decode_config(
+            environment.private_key.as_str(),
+            base64::URL_SAFE_NO_PAD,
+        )
+        .wrap_err("vapid private key should be base64url encoded")?;
+
+        let signing_key = SigningKey::from_slice(&private_key)
+            .map_err(|_| eyre!("vapid private key should be a P-256 private key"))?;
+
+        Ok(Self {
+            signing_key,
+            subject: environment.subject,
+        })
+    }
+
+    /// Base64url encoded public key, for browsers to subscribe with as the
+    /// `applicationServerKey`
+    pub fn public_key(&self) -> String {
+        base64::encode_config(
+            self.signing_key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes(),
+            base64::URL_SAFE_NO_PAD,
+        )
+    }
+
+    /// The `Authorization` header for a push service, as described by RFC 8292
+    fn authorization(&self, endpoint: &Url) -> String {
+        let encode = |bytes: &[u8]| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+
+        let header = encode(br#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = encode(
+            json!({
+                "aud": endpoint.origin().ascii_serialization(),
+                "exp": (OffsetDateTime::now_utc() + VAPID_TOKEN_LIFETIME).unix_timestamp(),
+                "sub": self.subject,
+            })
+            .to_string()
+            .as_bytes(),
+        );
+
+        let signing_input = format!("{header}.{claims}");
+        let signature: Signature = self.signing_key.sign(signing_input.as_bytes());
+
+        format!(
+            "vapid t={signing_input}.{}, k={}",
+            encode(&signature.to_bytes()),
+            self.public_key()
+        )
+    }
+}
+
+/// Whether the endpoint belongs to a browser's push service, anything else would have the server
+/// sending requests wherever it is told to
+pub fn is_push_service(endpoint: &Url) -> bool {
+    let Some(host) = endpoint.host_str() else {
+        return false;
+    };
+
+    endpoint.scheme() == "https"
+        && PUSH_SERVICE_HOSTS.iter().any(|push_service| {
+            host == *push_service
+                || host
+                    .strip_suffix(push_service)
+                    .map_or(false, |subdomain| subdomain.ends_with('.'))
+        })
+}
+
+/// What the service worker turns into a notification
+#[derive(Debug, Serialize)]
+struct PushMessage<'m> {
+    title: String,
+    body: &'m str,
+    url: &'m str,
+    icon: &'m str,
+    /// Replaces an earlier notification for the same creator
+    tag: String,
+}
+
+/// Send a push notification to everyone following a creator when they go live for the campaign
+#[tracing::instrument(skip_all)]
+pub async fn web_push_notifier(
+    http_client: reqwest::Client,
+    vapid: Vapid,
+    config: &Config,
+    store: Store,
+    events: broadcast::Receiver<ChangeEvent>,
+    watcher_data: watch::Receiver<WatcherDataReceive>,
+) {
+    info!("sending web push notifications");
+
+    let mut announcements =
+        Announcements::new("web push", config, store.clone(), events, watcher_data);
+
+    while let Some(announcement) = announcements.next().await {
+        let Announcement::Live {
+            creator,
+            stream,
+            campaign_name,
+            ..
+        } = &announcement
+        else {
+            continue;
+        };
+
+        let creator_key = creator.key();
+
+        let followers = {
+            let creator_key = creator_key.clone();
+
+            store
+                .with_connection(move |connection| push::followers(connection, &creator_key))
+                .await
+        };
+
+        let followers = match followers {
+            Ok(followers) => followers,
+            Err(error) => {
+                error!(?error, "failed to look up followers");
+                announcements.failed(&announcement).await;
+                continue;
+            }
+        };
+
+        let message = PushMessage {
+            title: format!("{} is live for {campaign_name}", creator.display_name),
+            body: &stream.title,
+            url: &stream.href,
+            icon: &creator.icon_url,
+            tag: creator_key.to_string(),
+        };
+
+        let message = match serde_json::to_vec(&message) {
+            Ok(message) => message,
+            Err(error) => {
+                error!(?error, "failed to serialize push message");
+                continue;
+            }
+        };
+
+        let expired = futures::stream::iter(&followers)
+            .map(|subscription| {
+                let http_client = &http_client;
+                let vapid = &vapid;
+                let message = &message;
+
+                async move {
+                    match send(http_client, vapid, subscription, message).await {
+                        Ok(Delivery::Sent) => None,
+                        Ok(Delivery::Expired) => Some(subscription.endpoint.clone()),
+                        Err(error) => {
+                            warn!(?error, "failed to send push notification");
+                            None
+                        }
+                    }
+                }
+            })
+            .buffer_unordered(MAX_CONCURRENT_SENDS)
+            .filter_map(|expired| async move { expired })
+            .collect::<Vec<_>>()
+            .await;
+
+        debug!(
+            creator = %creator_key,
+            followers = followers.len(),
+            expired = expired.len(),
+            "sent web push notifications"
+        );
+
+        if expired.is_empty() {
+            continue;
+        }
+
+        // The browser unsubscribed or the user revoked permission, so they can never be
+        // reached again
+        let result = store
+            .with_connection(move |connection| push::remove(connection, &expired))
+            .await;
+
+        if let Err(error) = result {
+            error!(?error, "failed to remove expired push subscriptions");
+        }
+    }
+}
+
+enum Delivery {
+    Sent,
+    /// The subscription is gone for good
+    Expired,
+}
+
+async fn send(
+    http_client: &reqwest::Client,
+    vapid: &Vapid,
+    subscription: &PushSubscription,
+    message: &[u8],
+) -> color_eyre::Result<Delivery> {
+    let endpoint = Url::parse(&subscription.endpoint).wrap_err("invalid endpoint")?;
+
+    // Subscriptions stored before endpoints were checked may point anywhere
+    if !is_push_service(&endpoint) {
+        bail!("endpoint is not a known push service");
+    }
+    let body = encrypt(subscription, message).wrap_err("failed to encrypt push message")?;
+
+    let response = http_client
+        .post(endpoint.clone())
+        .header("authorization", vapid.authorization(&endpoint))
+        .header("content-encoding", "aes128gcm")
+        .header("content-type", "application/octet-stream")
+        .header("ttl", TTL.as_secs())
+        .header("urgency", "high")
+        .body(body)
+        .send()
+        .await
+        // The endpoint identifies the subscriber
+        .map_err(|error| error.without_url())
+        .wrap_err("failed to send push message")?;
+
+    match response.status() {
+        status if status.is_success() => {
+            trace!("sent push message");
+            Ok(Delivery::Sent)
+        }
+        StatusCode::NOT_FOUND | StatusCode::GONE => Ok(Delivery::Expired),
+        status => bail!("push service returned a non-success HTTP code: {status}"),
+    }
+}
+
+/// Encrypt a message for a subscription, as described by RFC 8291
+fn encrypt(subscription: &PushSubscription, message: &[u8]) -> color_eyre::Result<Vec<u8>> {
+    let mut salt = [0; 16];
+    OsRng.fill_bytes(&mut salt);
+
+    encrypt_with(subscription, message, &SecretKey::random(&mut OsRng), salt)
+}
+
+fn encrypt_with(
+    subscription: &PushSubscription,
+    message: &[u8],
+    application_server_key: &SecretKey,
+    salt: [u8; 16],
+) -> color_eyre::Result<Vec<u8>> {
+    let user_agent_public =
+        base64::decode_config(&subscription.keys.p256dh, base64::URL_SAFE_NO_PAD)
+            .wrap_err("p256dh should be base64url encoded")?;
+    let auth_secret = base64::decode_config(&subscription.keys.auth, base64::URL_SAFE_NO_PAD)
+        .wrap_err("auth should be base64url encoded")?;
+
+    let user_agent_key = PublicKey::from_sec1_bytes(&user_agent_public)
+        .map_err(|_| eyre!("p256dh should be a P-256 public key"))?;
+    let application_server_public = application_server_key
+        .public_key()
+        .to_encoded_point(false);
+
+    let shared_secret = p256::ecdh::diffie_hellman(
+        application_server_key.to_nonzero_scalar(),
+        user_agent_key.as_affine(),
+    );
+
+    // Mix the authentication secret into the shared secret
+    let key_info = [
+        b"WebPush: info\0".as_slice(),
+        &user_agent_public,
+        application_server_public.as_bytes(),
+    ]
+    .concat();
+
+    let mut input_keying_material = [0; 32];
+    Hkdf::<Sha256>::new(Some(&auth_secret), shared_secret.raw_secret_bytes())
+        .expand(&key_info, &mut input_keying_material)
+        .map_err(|_| eyre!("keying material should be a valid length"))?;
+
+    // Derive the content encryption key and nonce, as described by RFC 8188
+    let content_keys = Hkdf::<Sha256>::new(Some(&salt), &input_keying_material);
+
+    let mut content_encryption_key = [0; 16];
+    content_keys
+        .expand(b"Content-Encoding: aes128gcm\0", &mut content_encryption_key)
+        .map_err(|_| eyre!("content encryption key should be a valid length"))?;
+
+    let mut nonce = [0; 12];
+    content_keys
+        .expand(b"Content-Encoding: nonce\0", &mut nonce)
+        .map_err(|_| eyre!("nonce should be a valid length"))?;
+
+    // A single record, ended by the last record delimiter
+    let plaintext = [message, &[2]].concat();
+
+    let ciphertext = Aes128Gcm::new_from_slice(&content_encryption_key)
+        .map_err(|_| eyre!("content encryption key should be a valid length"))?
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| eyre!("failed to encrypt message"))?;
+
+    // The header, then the record
+    Ok([
+        salt.as_slice(),
+        &RECORD_SIZE.to_be_bytes(),
+        &[application_server_public.len() as u8],
+        application_server_public.as_bytes(),
+        &ciphertext,
+    ]
+    .concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::push::PushSubscriptionKeys;
+
+    fn decode(base64url: &str) -> Vec<u8> {
+        base64::decode_config(base64url, base64::URL_SAFE_NO_PAD)
+            .expect("test data should be base64url")
+    }
+
+    /// The example from RFC 8291 section 5
+    #[test]
+    fn encrypts_rfc_8291_example() {
+        let subscription = PushSubscription {
+            endpoint: "https://push.example.net/push/JzLQ3raZJfFBR0aqvOMsLrt54w4rJUsV".into(),
+            keys: PushSubscriptionKeys {
+                p256dh: "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4".into(),
+                auth: "BTBZMqHH6r4Tts7J_aSIgg".into(),
+            },
+        };
+        let application_server_key =
+            SecretKey::from_slice(&decode("yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw"))
+                .expect("example key should be valid");
+        let salt = decode("DGv6ra1nlYgDCS1FRnbzlw")
+            .try_into()
+            .expect("example salt should be 16 bytes");
+
+        let body = encrypt_with(
+            &subscription,
+            b"When I grow up, I want to be a watermelon",
+            &application_server_key,
+            salt,
+        )
+        .expect("example should encrypt");
+
+        assert_eq!(
+            base64::encode_config(body, base64::URL_SAFE_NO_PAD),
+            "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN"
+        );
+    }
+
+    #[test]
+    fn only_accepts_push_services() {
+        let accepts =
+            |url: &str| is_push_service(&Url::parse(url).expect("test url should be valid"));
+
+        assert!(accepts("https://fcm.googleapis.com/fcm/send/abc"));
+        assert!(accepts("https://updates.push.services.mozilla.com/wpush/v2/abc"));
+        assert!(accepts("https://web.push.apple.com/abc"));
+        assert!(accepts("https://wns2-par02p.notify.windows.com/w/?token=abc"));
+
+        assert!(!accepts("http://fcm.googleapis.com/fcm/send/abc"));
+        assert!(!accepts("https://evilpush.apple.com/abc"));
+        assert!(!accepts("https://push.apple.com.example.com/abc"));
+        assert!(!accepts("https://169.254.169.254/latest/meta-data"));
+        assert!(!accepts("https://localhost/abc"));
+    }
+}
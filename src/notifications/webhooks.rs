@@ -0,0 +1,693 @@
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::{eyre, Context};
+use hmac::{Hmac, Mac};
+use hyper::{header, HeaderMap, StatusCode};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use time::OffsetDateTime;
+use tokio::sync::{
+    broadcast,
+    mpsc::{self, error::TrySendError},
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    events::{Change, ChangeEvent},
+    metrics::types::{
+        DeliveryOutcome, OutgoingWebhookDeliveriesMetric, OutgoingWebhookDeliveryKey,
+        OutgoingWebhookKey, OutgoingWebhookLatencyMetric,
+    },
+    store::event_log::append_json_line,
+};
+
+/// Attempts at delivering an event before it is given up on
+const MAX_ATTEMPTS: u32 = 5;
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest `Retry-After` waited out, a partner asking for longer is given up on
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(5 * 60);
+/// Events waiting for a partner before new ones are dead lettered straight away
+const QUEUE_SIZE: usize = 256;
+
+#[aliri_braid::braid(serde, display = "omit", debug = "omit")]
+pub struct SigningSecret;
+
+impl Debug for SigningSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "*****")
+    }
+}
+
+fn default_dead_letter_path() -> PathBuf {
+    PathBuf::from("creatorsforacause.dead-letters.jsonl")
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OutgoingWebhooksEnvironment {
+    /// Path to a TOML file listing the webhook subscriptions, as `[[webhook]]` tables
+    #[serde(rename = "outgoing_webhooks_path")]
+    path: PathBuf,
+    /// Path to the JSON lines log of events that could not be delivered
+    #[serde(
+        rename = "outgoing_webhooks_dead_letter_path",
+        default = "default_dead_letter_path"
+    )]
+    dead_letter_path: PathBuf,
+}
+
+#[derive(Deserialize, Debug)]
+struct WebhooksFile {
+    #[serde(rename = "webhook", default)]
+    webhooks: Vec<Subscription>,
+}
+
+/// A partner's webhook, and the events they want to receive
+#[derive(Deserialize, Debug)]
+struct Subscription {
+    /// Identifies the webhook in logs and metrics
+    name: String,
+    url: Url,
+    /// Shared with the partner, to verify the signature of every delivery
+    secret: SigningSecret,
+    #[serde(default = "EventKind::all")]
+    events: Vec<EventKind>,
+}
+
+/// The events that can be subscribed to, named like the `type` of the payload
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum EventKind {
+    WentLive,
+    WentOffline,
+    AmountRaised,
+}
+
+impl EventKind {
+    fn all() -> Vec<Self> {
+        vec![
+            EventKind::WentLive,
+            EventKind::WentOffline,
+            EventKind::AmountRaised,
+        ]
+    }
+
+    fn of(change: &Change) -> Option<Self> {
+        match change {
+            Change::WentLive { .. } => Some(EventKind::WentLive),
+            Change::WentOffline { .. } => Some(EventKind::WentOffline),
+            Change::AmountRaised { .. } => Some(EventKind::AmountRaised),
+            _ => None,
+        }
+    }
+}
+
+/// An event that could not be delivered, kept so it can be looked into or replayed
+#[derive(Debug, Serialize)]
+struct DeadLetter<'d> {
+    #[serde(with = "time::serde::rfc3339")]
+    time: OffsetDateTime,
+    webhook: &'d str,
+    attempts: u32,
+    error: String,
+    event: &'d ChangeEvent,
+}
+
+/// Why an attempt failed, and whether it is worth trying again
+struct AttemptError {
+    error: color_eyre::Report,
+    retry: bool,
+    /// How long the partner asked to be left alone for
+    retry_after: Option<Duration>,
+}
+
+/// POST change events to partner webhooks, signed so they can be verified
+#[tracing::instrument(skip_all)]
+pub async fn webhook_sender(
+    http_client: reqwest::Client,
+    environment: OutgoingWebhooksEnvironment,
+    mut events: broadcast::Receiver<ChangeEvent>,
+    deliveries: OutgoingWebhookDeliveriesMetric,
+    latency: OutgoingWebhookLatencyMetric,
+) {
+    let subscriptions = match load(&environment.path).await {
+        Ok(subscriptions) => subscriptions,
+        Err(error) => {
+            error!(?error, "failed to load outgoing webhooks, not sending any");
+            return;
+        }
+    };
+
+    info!(webhooks = subscriptions.len(), "sending outgoing webhooks");
+
+    let dead_letter_path = &environment.dead_letter_path;
+
+    let (queues, in_order): (Vec<_>, Vec<_>) = subscriptions
+        .iter()
+        .map(|subscription| {
+            let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+
+            let in_order = deliver_in_order(
+                &http_client,
+                subscription,
+                receiver,
+                dead_letter_path,
+                &deliveries,
+                &latency,
+            );
+
+            ((subscription, sender), in_order)
+        })
+        .unzip();
+
+    let dispatch = async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(
+                        skipped,
+                        "outgoing webhooks fell behind, events were dropped"
+                    );
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+
+            let Some(kind) = EventKind::of(&event.change) else {
+                continue;
+            };
+
+            for (subscription, queue) in &queues {
+                if !subscription.events.contains(&kind) {
+                    continue;
+                }
+
+                // Waiting on a partner this far behind would hold up every other partner
+                if let Err(TrySendError::Full(event)) = queue.try_send(event.clone()) {
+                    error!(
+                        webhook = %subscription.name,
+                        "outgoing webhook queue is full, dead lettering"
+                    );
+
+                    dead_letter(
+                        dead_letter_path,
+                        subscription,
+                        0,
+                        &eyre!("too many events waiting to be delivered"),
+                        &event,
+                    )
+                    .await;
+                }
+            }
+        }
+
+        // Dropping the queues lets the deliveries finish what was already queued
+    };
+
+    // Partners are delivered to alongside each other, so one slow partner does not hold up
+    // the rest
+    tokio::join!(dispatch, futures::future::join_all(in_order));
+}
+
+/// Deliver the queued events one at a time, so the partner receives them in order
+async fn deliver_in_order(
+    http_client: &reqwest::Client,
+    subscription: &Subscription,
+    mut queue: mpsc::Receiver<ChangeEvent>,
+    dead_letter_path: &Path,
+    deliveries: &OutgoingWebhookDeliveriesMetric,
+    latency: &OutgoingWebhookLatencyMetric,
+) {
+    while let Some(event) = queue.recv().await {
+        deliver(
+            http_client,
+            subscription,
+            event,
+            dead_letter_path,
+            deliveries,
+            latency,
+        )
+        .await;
+    }
+}
+
+async fn load(path: &Path) -> color_eyre::Result<Vec<Subscription>> {
+    let file = tokio::fs::read_to_string(path)
+        .await
+        .wrap_err("failed to read outgoing webhooks file")?;
+
+    let WebhooksFile { webhooks } =
+        toml::from_str(&file).wrap_err("invalid outgoing webhooks file")?;
+
+    Ok(webhooks)
+}
+
+/// Deliver an event, backing off between attempts, and dead letter it if every attempt fails
+#[tracing::instrument(skip_all, fields(webhook = %subscription.name))]
+async fn deliver(
+    http_client: &reqwest::Client,
+    subscription: &Subscription,
+    event: ChangeEvent,
+    dead_letter_path: &Path,
+    deliveries: &OutgoingWebhookDeliveriesMetric,
+    latency: &OutgoingWebhookLatencyMetric,
+) {
+    // The same for every attempt, so partners can tell retries apart from new events
+    let delivery_id = hex::encode(rand::random::<[u8; 16]>());
+
+    let body = match serde_json::to_vec(&event) {
+        Ok(body) => body,
+        Err(error) => {
+            error!(?error, "failed to serialize outgoing webhook payload");
+            return;
+        }
+    };
+
+    let record = |outcome| {
+        deliveries
+            .get_or_create(&OutgoingWebhookDeliveryKey {
+                webhook: subscription.name.clone(),
+                outcome,
+            })
+            .inc();
+    };
+
+    let mut backoff = MIN_BACKOFF;
+    let mut attempt = 1;
+
+    let error = loop {
+        let start = Instant::now();
+        let result = attempt_delivery(http_client, subscription, &delivery_id, &body).await;
+
+        latency
+            .get_or_create(&OutgoingWebhookKey {
+                webhook: subscription.name.clone(),
+            })
+            .observe(start.elapsed().as_secs_f64());
+
+        match result {
+            Ok(()) => {
+                debug!(attempt, "delivered outgoing webhook");
+                record(DeliveryOutcome::Delivered);
+                return;
+            }
+            Err(AttemptError {
+                error,
+                retry,
+                retry_after,
+            }) if retry && attempt < MAX_ATTEMPTS => {
+                let wait = retry_after.unwrap_or(backoff);
+
+                warn!(
+                    ?error,
+                    attempt,
+                    ?wait,
+                    "outgoing webhook delivery failed, retrying"
+                );
+                record(DeliveryOutcome::Retried);
+
+                tokio::time::sleep(wait).await;
+            }
+            Err(AttemptError { error, .. }) => break error,
+        }
+
+        backoff *= 2;
+        attempt += 1;
+    };
+
+    error!(
+        ?error,
+        attempt, "outgoing webhook delivery failed, dead lettering"
+    );
+    record(DeliveryOutcome::DeadLettered);
+
+    dead_letter(dead_letter_path, subscription, attempt, &error, &event).await;
+}
+
+async fn dead_letter(
+    dead_letter_path: &Path,
+    subscription: &Subscription,
+    attempts: u32,
+    error: &color_eyre::Report,
+    event: &ChangeEvent,
+) {
+    let dead_letter = DeadLetter {
+        time: OffsetDateTime::now_utc(),
+        webhook: &subscription.name,
+        attempts,
+        error: format!("{error:#}"),
+        event,
+    };
+
+    if let Err(error) = append_json_line(dead_letter_path, &dead_letter).await {
+        error!(?error, "failed to write dead letter");
+    }
+}
+
+async fn attempt_delivery(
+    http_client: &reqwest::Client,
+    subscription: &Subscription,
+    delivery_id: &str,
+    body: &[u8],
+) -> Result<(), AttemptError> {
+    // Signed for every attempt, so receivers can reject stale timestamps
+    let timestamp = OffsetDateTime::now_utc().unix_timestamp().to_string();
+    let signature = sign(&subscription.secret, &timestamp, body);
+
+    let response = http_client
+        .post(subscription.url.clone())
+        .timeout(REQUEST_TIMEOUT)
+        .header("content-type", "application/json")
+        .header("x-delivery-id", delivery_id)
+        .header("x-signature-timestamp", &timestamp)
+        .header("x-signature", format!("sha256={signature}"))
+        .body(body.to_vec())
+        .send()
+        .await
+        .map_err(|error| AttemptError {
+            // The url may contain a token of the partner's
+            error: color_eyre::Report::new(error.without_url())
+                .wrap_err("failed to send outgoing webhook"),
+            retry: true,
+            retry_after: None,
+        })?;
+
+    let status = response.status();
+
+    if status.is_success() {
+        return Ok(());
+    }
+
+    let retry_after = (status == StatusCode::TOO_MANY_REQUESTS)
+        .then(|| retry_after(response.headers()))
+        .flatten();
+
+    Err(AttemptError {
+        // Anything else is a problem with the request, which would only fail again
+        retry: (status.is_server_error()
+            || status == StatusCode::TOO_MANY_REQUESTS
+            || status == StatusCode::REQUEST_TIMEOUT)
+            && retry_after.map_or(true, |retry_after| retry_after <= MAX_RETRY_AFTER),
+        retry_after,
+        error: eyre!("webhook returned a non-success HTTP code: {status}"),
+    })
+}
+
+/// The `Retry-After` header in seconds, the HTTP date form is not worth supporting
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// HMAC-SHA256 of the string `{timestamp}.{body}`, hex encoded. The same scheme tiltify uses
+/// for its webhooks, so partners only need to implement it once
+fn sign(secret: &SigningSecretRef, timestamp: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_str().as_bytes())
+        .expect("hmac should accept keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{body::Bytes, extract::State, routing::post, Router, Server};
+    use hyper::header::HeaderValue;
+    use rust_decimal::Decimal;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        metrics::types::outgoing_webhook_latency,
+        model::money::{CurrencyCode, Money},
+    };
+
+    const SECRET: &str = "partner-secret";
+
+    /// A status to respond with, and an optional `Retry-After`
+    type Response = (StatusCode, Option<&'static str>);
+
+    /// A request the stand-in partner received
+    struct Received {
+        headers: HeaderMap,
+        body: Bytes,
+    }
+
+    /// A local stand-in for a partner's webhook, giving the scripted responses before the usual
+    /// 204
+    #[derive(Clone, Default)]
+    struct Partner {
+        scripted: Arc<Mutex<VecDeque<Response>>>,
+        received: Arc<Mutex<Vec<Received>>>,
+    }
+
+    impl Partner {
+        async fn start(
+            scripted: impl IntoIterator<Item = Response>,
+        ) -> (Self, Subscription) {
+            let partner = Self {
+                scripted: Arc::new(Mutex::new(scripted.into_iter().collect())),
+                received: Arc::default(),
+            };
+
+            let app = Router::new()
+                .route("/webhook", post(Self::handler))
+                .with_state(partner.clone());
+
+            let server =
+                Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(app.into_make_service());
+            let address = server.local_addr();
+            tokio::spawn(server);
+
+            let url = format!("http://{address}/webhook");
+            let subscription = Subscription {
+                name: "partner".to_owned(),
+                url: Url::parse(&url).expect("webhook url should be valid"),
+                secret: SigningSecret::from_static(SECRET),
+                events: EventKind::all(),
+            };
+
+            (partner, subscription)
+        }
+
+        async fn handler(
+            State(partner): State<Self>,
+            headers: HeaderMap,
+            body: Bytes,
+        ) -> (StatusCode, HeaderMap) {
+            partner
+                .received
+                .lock()
+                .expect("partner lock should not be poisoned")
+                .push(Received { headers, body });
+
+            let (status, retry_after) = partner
+                .scripted
+                .lock()
+                .expect("partner lock should not be poisoned")
+                .pop_front()
+                .unwrap_or((StatusCode::NO_CONTENT, None));
+
+            let mut headers = HeaderMap::new();
+            if let Some(retry_after) = retry_after {
+                headers.insert(header::RETRY_AFTER, HeaderValue::from_static(retry_after));
+            }
+
+            (status, headers)
+        }
+
+        fn received(&self) -> std::sync::MutexGuard<'_, Vec<Received>> {
+            self.received
+                .lock()
+                .expect("partner lock should not be poisoned")
+        }
+    }
+
+    fn event(total: i64) -> ChangeEvent {
+        let money = |amount| Money::new(Decimal::new(amount, 0), CurrencyCode::from_static("USD"));
+
+        ChangeEvent {
+            time: OffsetDateTime::now_utc(),
+            change: Change::AmountRaised {
+                previous: money(total - 1),
+                total: money(total),
+            },
+        }
+    }
+
+    /// A dead letter log of its own for every test
+    fn dead_letter_path(test: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "creatorsforacause-webhooks-{test}-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        path
+    }
+
+    async fn deliver_event(subscription: &Subscription, dead_letter_path: &Path) {
+        deliver(
+            &reqwest::Client::new(),
+            subscription,
+            event(1_234),
+            dead_letter_path,
+            &OutgoingWebhookDeliveriesMetric::default(),
+            &OutgoingWebhookLatencyMetric::new_with_constructor(outgoing_webhook_latency),
+        )
+        .await;
+    }
+
+    fn dead_letters(path: &Path) -> Vec<Value> {
+        std::fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("dead letter should be valid JSON"))
+            .collect()
+    }
+
+    fn header_value<'r>(received: &'r Received, name: &str) -> &'r str {
+        received
+            .headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn signs_deliveries() {
+        let (partner, subscription) = Partner::start([]).await;
+        let path = dead_letter_path("signed");
+
+        deliver_event(&subscription, &path).await;
+
+        let received = partner.received();
+        assert_eq!(received.len(), 1);
+
+        let timestamp = header_value(&received[0], "x-signature-timestamp");
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes())
+            .expect("hmac should accept keys of any length");
+        mac.update(format!("{timestamp}.").as_bytes());
+        mac.update(&received[0].body);
+        let expected = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+        assert!(!timestamp.is_empty());
+        assert_eq!(header_value(&received[0], "x-signature"), expected);
+        assert_eq!(header_value(&received[0], "x-delivery-id").len(), 32);
+        assert!(dead_letters(&path).is_empty());
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_and_rate_limits() {
+        let (partner, subscription) = Partner::start([
+            (StatusCode::INTERNAL_SERVER_ERROR, None),
+            (StatusCode::TOO_MANY_REQUESTS, Some("0")),
+        ])
+        .await;
+        let path = dead_letter_path("retried");
+
+        let start = Instant::now();
+        deliver_event(&subscription, &path).await;
+        let elapsed = start.elapsed();
+
+        let received = partner.received();
+        let delivery_id = header_value(&received[0], "x-delivery-id");
+
+        assert_eq!(received.len(), 3);
+        assert!(received
+            .iter()
+            .all(|request| header_value(request, "x-delivery-id") == delivery_id));
+
+        // Backed off after the server error, but not after the rate limit which asked for none
+        assert!(elapsed >= MIN_BACKOFF);
+        assert!(elapsed < MIN_BACKOFF * 3);
+        assert!(dead_letters(&path).is_empty());
+    }
+
+    #[tokio::test]
+    async fn dead_letters_after_last_attempt() {
+        let (partner, subscription) =
+            Partner::start((0..MAX_ATTEMPTS).map(|_| (StatusCode::TOO_MANY_REQUESTS, Some("0"))))
+                .await;
+        let path = dead_letter_path("exhausted");
+
+        deliver_event(&subscription, &path).await;
+
+        assert_eq!(partner.received().len(), MAX_ATTEMPTS as usize);
+
+        let dead_letters = dead_letters(&path);
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0]["webhook"], "partner");
+        assert_eq!(dead_letters[0]["attempts"], MAX_ATTEMPTS);
+        assert_eq!(dead_letters[0]["event"]["type"], "amount_raised");
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_long_retry_after() {
+        let (partner, subscription) =
+            Partner::start([(StatusCode::TOO_MANY_REQUESTS, Some("3600"))]).await;
+        let path = dead_letter_path("long-retry-after");
+
+        deliver_event(&subscription, &path).await;
+
+        assert_eq!(partner.received().len(), 1);
+        assert_eq!(dead_letters(&path).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn delivers_in_order() {
+        let (partner, subscription) =
+            Partner::start([(StatusCode::SERVICE_UNAVAILABLE, None)]).await;
+        let path = dead_letter_path("ordered");
+        let events = [event(1), event(2), event(3)];
+
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        for event in &events {
+            sender
+                .try_send(event.clone())
+                .expect("queue should have room");
+        }
+        drop(sender);
+
+        deliver_in_order(
+            &reqwest::Client::new(),
+            &subscription,
+            receiver,
+            &path,
+            &OutgoingWebhookDeliveriesMetric::default(),
+            &OutgoingWebhookLatencyMetric::new_with_constructor(outgoing_webhook_latency),
+        )
+        .await;
+
+        let bodies = partner
+            .received()
+            .iter()
+            .map(|request| request.body.to_vec())
+            .collect::<Vec<_>>();
+        let expected = [&events[0], &events[0], &events[1], &events[2]]
+            .map(|event| serde_json::to_vec(event).expect("event should serialize"));
+
+        // The first event is retried before the others are sent
+        assert_eq!(bodies, expected);
+    }
+}
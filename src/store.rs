@@ -0,0 +1,331 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use color_eyre::eyre::Context;
+use rusqlite::{types::Type, Connection, Row};
+use serde::Deserialize;
+use time::{Duration, OffsetDateTime};
+use tokio::sync::watch;
+use tracing::{error, info, trace};
+
+use crate::{config::Config, model::CreatorKey, watcher::WatcherDataReceive};
+
+use self::{
+    campaign::AmountSample,
+    pace::{Pace, RATE_WINDOW},
+    sessions::LiveSample,
+};
+
+pub mod announcements;
+pub mod campaign;
+pub mod event_log;
+pub mod pace;
+pub mod push;
+pub mod sessions;
+pub mod snapshot;
+
+/// Schema changes, applied in order and tracked with `PRAGMA user_version`.
+/// Never edit a migration once released, append a new one instead
+const MIGRATIONS: &[&str] = &[
+    // 1: stream sessions
+    "
+    CREATE TABLE stream_sessions (
+        id INTEGER PRIMARY KEY,
+        service TEXT NOT NULL,
+        creator_id TEXT NOT NULL,
+        href TEXT NOT NULL,
+        started_at INTEGER NOT NULL,
+        ended_at INTEGER,
+        last_seen_at INTEGER NOT NULL,
+        campaign INTEGER NOT NULL,
+        peak_viewers INTEGER,
+        viewer_total INTEGER NOT NULL,
+        viewer_samples INTEGER NOT NULL,
+        samples INTEGER NOT NULL,
+        UNIQUE (service, creator_id, href, started_at)
+    );
+
+    CREATE INDEX stream_sessions_open ON stream_sessions (ended_at) WHERE ended_at IS NULL;
+
+    CREATE TABLE stream_session_titles (
+        session_id INTEGER NOT NULL REFERENCES stream_sessions (id) ON DELETE CASCADE,
+        title TEXT NOT NULL,
+        UNIQUE (session_id, title)
+    );
+    ",
+    // 2: amount raised over time
+    "
+    CREATE TABLE campaign_samples (
+        campaign_id INTEGER NOT NULL,
+        sampled_at INTEGER NOT NULL,
+        amount TEXT NOT NULL,
+        currency TEXT NOT NULL
+    );
+
+    CREATE INDEX campaign_samples_time ON campaign_samples (campaign_id, sampled_at);
+    ",
+    // 3: announcements already made
+    "
+    CREATE TABLE announcements (
+        channel TEXT NOT NULL,
+        key TEXT NOT NULL,
+        announced_at INTEGER NOT NULL,
+        PRIMARY KEY (channel, key)
+    );
+    ",
+    // 4: web push subscriptions
+    "
+    CREATE TABLE push_subscriptions (
+        endpoint TEXT PRIMARY KEY,
+        p256dh TEXT NOT NULL,
+        auth TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE push_follows (
+        endpoint TEXT NOT NULL REFERENCES push_subscriptions (endpoint) ON DELETE CASCADE,
+        service TEXT NOT NULL,
+        creator_id TEXT NOT NULL,
+        PRIMARY KEY (endpoint, service, creator_id)
+    );
+
+    CREATE INDEX push_follows_creator ON push_follows (service, creator_id);
+    ",
+];
+
+/// Most points to show on the dashboard's progress chart
+const CHART_POINTS: u32 = 200;
+
+fn default_database_path() -> PathBuf {
+    PathBuf::from("creatorsforacause.sqlite3")
+}
+
+fn default_event_log_path() -> PathBuf {
+    PathBuf::from("creatorsforacause.events.jsonl")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StoreEnvironment {
+    /// Path to the sqlite database holding history that outlives restarts
+    #[serde(rename = "database_path", default = "default_database_path")]
+    pub database_path: PathBuf,
+    /// Path to the snapshot of the latest data, served until the first refresh after a restart.
+    /// Defaults to next to the database, so it lives on the same volume
+    #[serde(rename = "snapshot_path")]
+    snapshot_path: Option<PathBuf>,
+    /// Path to the JSON lines log of every change event
+    #[serde(rename = "event_log_path", default = "default_event_log_path")]
+    pub event_log_path: PathBuf,
+}
+
+impl StoreEnvironment {
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.snapshot_path.clone().unwrap_or_else(|| {
+            self.database_path
+                .with_file_name("creatorsforacause.snapshot.json")
+        })
+    }
+}
+
+/// What the dashboard shows from the recorded history
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct HistorySummary {
+    /// Time streamed for the campaign by each creator
+    pub streamed_time: HashMap<CreatorKey, Duration>,
+    /// The amount raised over time, downsampled for charting
+    pub amount_raised: Vec<AmountSample>,
+    /// Missing until there is enough history to tell
+    pub pace: Option<Pace>,
+}
+
+pub type HistorySummaryReceive = Arc<HistorySummary>;
+
+/// A persistent store backed by sqlite
+#[derive(Clone)]
+pub struct Store {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Store {
+    #[tracing::instrument]
+    pub fn open(path: &Path) -> color_eyre::Result<Self> {
+        let mut connection = Connection::open(path).wrap_err("failed to open database")?;
+
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .wrap_err("failed to enable write ahead logging")?;
+        connection
+            .pragma_update(None, "foreign_keys", true)
+            .wrap_err("failed to enable foreign keys")?;
+
+        migrate(&mut connection).wrap_err("failed to migrate database")?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Run queries on the blocking thread pool, since sqlite is synchronous
+    pub async fn with_connection<T, F>(&self, f: F) -> color_eyre::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection
+                .lock()
+                .expect("database connection should not be poisoned");
+
+            f(&mut connection)
+        })
+        .await
+        .wrap_err("database task panicked")?
+        .wrap_err("database query failed")
+    }
+}
+
+fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+
+        transaction.commit()?;
+
+        info!(version = index + 1, "applied database migration");
+    }
+
+    Ok(())
+}
+
+/// Record the live streams and amount raised of every refresh, and keep the summary shown
+/// on the dashboard up to date
+#[tracing::instrument(skip_all)]
+pub async fn history_recorder(
+    store: Store,
+    config: &Config,
+    watcher_data: watch::Receiver<WatcherDataReceive>,
+    summary: watch::Sender<HistorySummaryReceive>,
+) {
+    // Sample on the interval alone, so bursts of pushed updates do not weigh more than
+    // quiet periods, and unchanged data that is not republished is still sampled
+    let mut interval = tokio::time::interval(config.refresh_period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        interval.tick().await;
+
+        if watcher_data.has_changed().is_err() {
+            // Channel closed
+            break;
+        }
+
+        // Restored streams are not known to still be live
+        let Some(data) = watcher_data.borrow().clone().filter(|data| !data.restored) else {
+            continue;
+        };
+
+        let now = OffsetDateTime::now_utc();
+        let campaign = data.tiltify.clone();
+        let campaign_id = campaign.id;
+        let campaign_start = campaign.starts_at;
+        let amount_raised = campaign.total_amount_raised.clone();
+        let live_creators = data
+            .creators
+            .iter()
+            .filter(|creator| creator.streams.iter().any(|stream| stream.campaign))
+            .count();
+
+        let samples = data
+            .creators
+            .iter()
+            .flat_map(|creator| {
+                creator.streams.iter().map(|stream| LiveSample {
+                    creator: creator.key(),
+                    href: stream.href.clone(),
+                    title: stream.title.clone(),
+                    started_at: stream.start_time,
+                    viewers: stream.viewers,
+                    campaign: stream.campaign,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let result = store
+            .with_connection(move |connection| {
+                sessions::record(connection, now, &samples)?;
+                campaign::record(connection, campaign_id, now, &amount_raised)?;
+
+                // Samples are only recorded on changes, so the latest amount still holds now
+                let current = AmountSample {
+                    time: now,
+                    amount: amount_raised,
+                };
+
+                let with_current = |mut samples: Vec<AmountSample>| {
+                    if samples.last() != Some(&current) {
+                        samples.push(current.clone());
+                    }
+                    samples
+                };
+
+                let recent = campaign::samples_since(connection, campaign_id, now - RATE_WINDOW)?;
+                let bucket = ((now - campaign_start) / CHART_POINTS).max(Duration::MINUTE);
+                let chart = campaign::samples(connection, campaign_id, bucket, CHART_POINTS)?;
+
+                Ok(HistorySummary {
+                    pace: Pace::new(&with_current(recent), &campaign, live_creators, now),
+                    streamed_time: sessions::streamed_time(connection, campaign_start)?,
+                    amount_raised: with_current(chart),
+                })
+            })
+            .await;
+
+        match result {
+            Ok(history_summary) => {
+                trace!("recorded history");
+
+                summary.send_if_modified(|current| {
+                    if **current == history_summary {
+                        return false;
+                    }
+
+                    *current = Arc::new(history_summary);
+
+                    true
+                });
+            }
+            Err(error) => {
+                error!(?error, "failed to record history");
+            }
+        }
+    }
+}
+
+/// Read a column of unix seconds
+fn timestamp(row: &Row, index: usize) -> rusqlite::Result<OffsetDateTime> {
+    OffsetDateTime::from_unix_timestamp(row.get(index)?).map_err(|error| {
+        rusqlite::Error::FromSqlConversionFailure(index, Type::Integer, Box::new(error))
+    })
+}
+
+/// Read a text column through its `FromStr` implementation
+fn parsed<T>(row: &Row, index: usize) -> rusqlite::Result<T>
+where
+    T: FromStr,
+    T::Err: Error + Send + Sync + 'static,
+{
+    row.get::<_, String>(index)?.parse().map_err(|error| {
+        rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(error))
+    })
+}
@@ -0,0 +1,26 @@
+use rusqlite::{params, Connection};
+use time::OffsetDateTime;
+
+/// Claim an announcement for a channel, false if it was already made
+pub fn claim(connection: &Connection, channel: &str, key: &str) -> rusqlite::Result<bool> {
+    let inserted = connection
+        .prepare_cached(
+            "INSERT OR IGNORE INTO announcements (channel, key, announced_at) VALUES (?1, ?2, ?3)",
+        )?
+        .execute(params![
+            channel,
+            key,
+            OffsetDateTime::now_utc().unix_timestamp()
+        ])?;
+
+    Ok(inserted == 1)
+}
+
+/// Give up a claim on an announcement that could not be made
+pub fn release(connection: &Connection, channel: &str, key: &str) -> rusqlite::Result<()> {
+    connection
+        .prepare_cached("DELETE FROM announcements WHERE channel = ?1 AND key = ?2")?
+        .execute(params![channel, key])?;
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::model::money::{CurrencyCode, Money};
+
+use super::{parsed, timestamp};
+
+/// The total amount raised by the campaign at a point in time
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AmountSample {
+    #[serde(with = "time::serde::rfc3339")]
+    pub time: OffsetDateTime,
+    pub amount: Money,
+}
+
+/// Record the amount raised, unless it is the same as the last recorded amount
+pub fn record(
+    connection: &Connection,
+    campaign_id: u32,
+    time: OffsetDateTime,
+    amount: &Money,
+) -> rusqlite::Result<()> {
+    connection
+        .prepare_cached(
+            "
+            INSERT INTO campaign_samples (campaign_id, sampled_at, amount, currency)
+            SELECT ?1, ?2, ?3, ?4
+            WHERE NOT EXISTS (
+                SELECT 1
+                FROM (
+                    SELECT amount, currency
+                    FROM campaign_samples
+                    WHERE campaign_id = ?1
+                    ORDER BY sampled_at DESC
+                    LIMIT 1
+                )
+                WHERE amount = ?3 AND currency = ?4
+            )
+            ",
+        )?
+        .execute(params![
+            campaign_id,
+            time.unix_timestamp(),
+            // Stored as text to avoid any loss of precision, normalized so equal amounts
+            // compare equal whatever their scale
+            amount.amount.normalize().to_string(),
+            amount.currency.as_str(),
+        ])?;
+
+    Ok(())
+}
+
+/// The last sample of each time bucket, for at most `limit` of the most recent buckets,
+/// oldest first
+pub fn samples(
+    connection: &Connection,
+    campaign_id: u32,
+    bucket: Duration,
+    limit: u32,
+) -> rusqlite::Result<Vec<AmountSample>> {
+    // The other columns of an aggregate query come from the row max() picked
+    let mut samples = connection
+        .prepare_cached(
+            "
+            SELECT max(sampled_at), amount, currency
+            FROM campaign_samples
+            WHERE campaign_id = ?1
+            GROUP BY sampled_at / ?2
+            ORDER BY 1 DESC
+            LIMIT ?3
+            ",
+        )?
+        .query_map(
+            params![campaign_id, bucket.whole_seconds().max(1), limit],
+            sample_from_row,
+        )?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    samples.reverse();
+
+    Ok(samples)
+}
+
+/// The first sample, and every sample from the last one at or before `since`, oldest first
+pub fn samples_since(
+    connection: &Connection,
+    campaign_id: u32,
+    since: OffsetDateTime,
+) -> rusqlite::Result<Vec<AmountSample>> {
+    connection
+        .prepare_cached(
+            "
+            SELECT sampled_at, amount, currency
+            FROM campaign_samples
+            WHERE campaign_id = ?1 AND (
+                sampled_at >= coalesce((
+                    SELECT max(sampled_at)
+                    FROM campaign_samples
+                    WHERE campaign_id = ?1 AND sampled_at <= ?2
+                ), 0)
+                OR sampled_at = (
+                    SELECT min(sampled_at) FROM campaign_samples WHERE campaign_id = ?1
+                )
+            )
+            ORDER BY sampled_at
+            ",
+        )?
+        .query_map(
+            params![campaign_id, since.unix_timestamp()],
+            sample_from_row,
+        )?
+        .collect()
+}
+
+fn sample_from_row(row: &Row) -> rusqlite::Result<AmountSample> {
+    Ok(AmountSample {
+        time: timestamp(row, 0)?,
+        amount: Money::new(
+            parsed(row, 1)?,
+            CurrencyCode::from(row.get::<_, String>(2)?),
+        ),
+    })
+}
@@ -0,0 +1,50 @@
+use std::path::Path;
+
+use color_eyre::eyre::Context;
+use serde::Serialize;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt, sync::broadcast};
+use tracing::{error, trace, warn};
+
+use crate::events::ChangeEvent;
+
+/// Append every change event to a JSON lines file, one event per line
+#[tracing::instrument(skip(events))]
+pub async fn event_logger(path: &Path, mut events: broadcast::Receiver<ChangeEvent>) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!(skipped, "event log fell behind, events were not logged");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        match append_json_line(path, &event).await {
+            Ok(()) => trace!("logged change event"),
+            Err(error) => error!(?error, "failed to log change event"),
+        }
+    }
+}
+
+/// Append a value to a JSON lines file
+pub async fn append_json_line<T: Serialize>(path: &Path, value: &T) -> color_eyre::Result<()> {
+    let mut line = serde_json::to_vec(value).wrap_err("failed to serialize line")?;
+    line.push(b'\n');
+
+    // Reopened for every line, so the log can be rotated without a restart
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .wrap_err("failed to open log")?;
+
+    // A single write, so lines are never interleaved
+    file.write_all(&line)
+        .await
+        .wrap_err("failed to write to log")?;
+
+    // Tokio finishes writes in the background, only a flush waits for the line to land
+    file.flush().await.wrap_err("failed to flush log")
+}
@@ -0,0 +1,116 @@
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+
+use crate::model::{money::Money, Campaign};
+
+use super::campaign::AmountSample;
+
+/// Recent window the current rate is measured over
+pub const RATE_WINDOW: Duration = Duration::hours(6);
+/// Goals further out than this are not worth projecting
+const MAX_PROJECTION: Duration = Duration::days(365);
+
+/// How quickly the campaign is raising money, and where that leads
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct Pace {
+    /// Raised per hour over the recent window
+    pub hourly_rate: Money,
+    /// Raised per hour since the first sample
+    pub average_hourly_rate: Money,
+    /// The recent rate averaged over the creators live for the campaign. Donations are not
+    /// attributed to streams, so this is an even split rather than what any one of them raised
+    pub hourly_rate_per_live_creator: Option<Money>,
+    pub live_creators: usize,
+    /// The total raised by the end of the campaign at the recent rate
+    pub projected_total: Option<Money>,
+    /// When the goal will be reached at the recent rate, missing if it already has been
+    /// or never will be
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub projected_goal_time: Option<OffsetDateTime>,
+    pub goal_reached: bool,
+}
+
+impl Pace {
+    /// Compute the pace from the samples of the campaign, oldest first, the last of which
+    /// should be the current amount
+    pub fn new(
+        samples: &[AmountSample],
+        campaign: &Campaign,
+        live_creators: usize,
+        now: OffsetDateTime,
+    ) -> Option<Self> {
+        let current = samples.last()?;
+        let currency = current.amount.currency.clone();
+
+        // Samples in another currency cannot be compared
+        let samples = samples
+            .iter()
+            .filter(|sample| sample.amount.currency == currency)
+            .collect::<Vec<_>>();
+
+        let first = samples.first()?;
+        // Samples are only recorded when the amount changes, so the last one from before the
+        // window is still the amount at its start
+        let window_start = match samples
+            .iter()
+            .rev()
+            .find(|sample| sample.time <= current.time - RATE_WINDOW)
+        {
+            Some(before) => AmountSample {
+                time: current.time - RATE_WINDOW,
+                amount: before.amount.clone(),
+            },
+            None => (*first).clone(),
+        };
+
+        let hourly_rate = hourly_rate(&window_start, current)?;
+        let average_hourly_rate = hourly_rate(first, current).unwrap_or(Decimal::ZERO);
+
+        let goal = &campaign.fundraiser_goal_amount;
+        let goal_reached = goal.currency == currency && current.amount.amount >= goal.amount;
+
+        let projected_total = campaign
+            .ends_at
+            .filter(|ends_at| *ends_at > now)
+            .map(|ends_at| {
+                Money::new(
+                    current.amount.amount + hourly_rate * hours(ends_at - now),
+                    currency.clone(),
+                )
+            });
+
+        let projected_goal_time =
+            (!goal_reached && goal.currency == currency && hourly_rate > Decimal::ZERO)
+                .then(|| {
+                    let remaining_hours = (goal.amount - current.amount.amount) / hourly_rate;
+                    let remaining_seconds = remaining_hours.to_f64()? * 60.0 * 60.0;
+
+                    (remaining_seconds < MAX_PROJECTION.as_seconds_f64())
+                        .then(|| now + Duration::seconds_f64(remaining_seconds))
+                })
+                .flatten();
+
+        Some(Self {
+            hourly_rate_per_live_creator: (live_creators > 0)
+                .then(|| Money::new(hourly_rate / Decimal::from(live_creators), currency.clone())),
+            hourly_rate: Money::new(hourly_rate, currency.clone()),
+            average_hourly_rate: Money::new(average_hourly_rate, currency),
+            live_creators,
+            projected_total,
+            projected_goal_time,
+            goal_reached,
+        })
+    }
+}
+
+fn hours(duration: Duration) -> Decimal {
+    Decimal::from(duration.whole_seconds()) / Decimal::from(60 * 60)
+}
+
+/// Rate between two samples, if they are far enough apart to tell
+fn hourly_rate(from: &AmountSample, to: &AmountSample) -> Option<Decimal> {
+    let hours = hours(to.time - from.time);
+
+    (hours > Decimal::ZERO).then(|| (to.amount.amount - from.amount.amount) / hours)
+}
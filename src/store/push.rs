@@ -0,0 +1,172 @@
+use rusqlite::{params, Connection};
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use crate::model::CreatorKey;
+
+/// A browser's push subscription, as serialized by `PushSubscription.toJSON()`
+#[derive(Debug, Deserialize, Clone)]
+pub struct PushSubscription {
+    pub endpoint: String,
+    pub keys: PushSubscriptionKeys,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PushSubscriptionKeys {
+    /// Base64url encoded P-256 public key of the browser
+    pub p256dh: String,
+    /// Base64url encoded authentication secret
+    pub auth: String,
+}
+
+/// Most subscriptions kept at once, every one of them is sent a request when a creator goes live
+const MAX_SUBSCRIPTIONS: u32 = 50_000;
+/// Most creators a single subscription can follow
+const MAX_FOLLOWS: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FollowOutcome {
+    Followed,
+    TooManySubscriptions,
+    TooManyFollows,
+}
+
+/// Follow the creators, replacing the keys of the subscription if it is already known
+pub fn follow(
+    connection: &mut Connection,
+    subscription: &PushSubscription,
+    creators: &[CreatorKey],
+) -> rusqlite::Result<FollowOutcome> {
+    let transaction = connection.transaction()?;
+
+    let known: bool = transaction
+        .prepare_cached("SELECT EXISTS (SELECT 1 FROM push_subscriptions WHERE endpoint = ?1)")?
+        .query_row(params![subscription.endpoint], |row| row.get(0))?;
+
+    if !known {
+        let subscriptions: u32 = transaction
+            .prepare_cached("SELECT COUNT(*) FROM push_subscriptions")?
+            .query_row([], |row| row.get(0))?;
+
+        if subscriptions >= MAX_SUBSCRIPTIONS {
+            return Ok(FollowOutcome::TooManySubscriptions);
+        }
+    }
+
+    transaction
+        .prepare_cached(
+            "
+            INSERT INTO push_subscriptions (endpoint, p256dh, auth, created_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (endpoint) DO UPDATE SET
+                p256dh = excluded.p256dh,
+                auth = excluded.auth
+            ",
+        )?
+        .execute(params![
+            subscription.endpoint,
+            subscription.keys.p256dh,
+            subscription.keys.auth,
+            OffsetDateTime::now_utc().unix_timestamp(),
+        ])?;
+
+    {
+        let mut insert_follow = transaction.prepare_cached(
+            "INSERT OR IGNORE INTO push_follows (endpoint, service, creator_id) VALUES (?1, ?2, ?3)",
+        )?;
+
+        for creator in creators {
+            insert_follow.execute(params![
+                subscription.endpoint,
+                creator.service.to_string(),
+                creator.id,
+            ])?;
+        }
+    }
+
+    let follows: u32 = transaction
+        .prepare_cached("SELECT COUNT(*) FROM push_follows WHERE endpoint = ?1")?
+        .query_row(params![subscription.endpoint], |row| row.get(0))?;
+
+    // Dropping the transaction rolls everything back
+    if follows > MAX_FOLLOWS {
+        return Ok(FollowOutcome::TooManyFollows);
+    }
+
+    transaction.commit()?;
+
+    Ok(FollowOutcome::Followed)
+}
+
+/// Stop following the creators, forgetting the subscription once it follows nobody
+pub fn unfollow(
+    connection: &mut Connection,
+    endpoint: &str,
+    creators: &[CreatorKey],
+) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+
+    {
+        let mut delete_follow = transaction.prepare_cached(
+            "DELETE FROM push_follows WHERE endpoint = ?1 AND service = ?2 AND creator_id = ?3",
+        )?;
+
+        for creator in creators {
+            delete_follow.execute(params![endpoint, creator.service.to_string(), creator.id])?;
+        }
+    }
+
+    transaction
+        .prepare_cached(
+            "
+            DELETE FROM push_subscriptions
+            WHERE endpoint = ?1
+                AND NOT EXISTS (SELECT 1 FROM push_follows WHERE endpoint = ?1)
+            ",
+        )?
+        .execute(params![endpoint])?;
+
+    transaction.commit()
+}
+
+/// Every subscription following the creator
+pub fn followers(
+    connection: &Connection,
+    creator: &CreatorKey,
+) -> rusqlite::Result<Vec<PushSubscription>> {
+    connection
+        .prepare_cached(
+            "
+            SELECT push_subscriptions.endpoint, p256dh, auth
+            FROM push_follows
+            JOIN push_subscriptions USING (endpoint)
+            WHERE service = ?1 AND creator_id = ?2
+            ",
+        )?
+        .query_map(params![creator.service.to_string(), creator.id], |row| {
+            Ok(PushSubscription {
+                endpoint: row.get(0)?,
+                keys: PushSubscriptionKeys {
+                    p256dh: row.get(1)?,
+                    auth: row.get(2)?,
+                },
+            })
+        })?
+        .collect()
+}
+
+/// Forget subscriptions that can no longer be reached, along with everything they follow
+pub fn remove(connection: &mut Connection, endpoints: &[String]) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+
+    {
+        let mut delete_subscription =
+            transaction.prepare_cached("DELETE FROM push_subscriptions WHERE endpoint = ?1")?;
+
+        for endpoint in endpoints {
+            delete_subscription.execute(params![endpoint])?;
+        }
+    }
+
+    transaction.commit()
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+
+use crate::model::{CreatorKey, StreamingService};
+
+use super::{parsed, timestamp};
+
+/// A live stream as seen during a single refresh
+#[derive(Debug)]
+pub struct LiveSample {
+    pub creator: CreatorKey,
+    pub href: String,
+    pub title: String,
+    pub started_at: OffsetDateTime,
+    pub viewers: Option<u32>,
+    pub campaign: bool,
+}
+
+/// A single stream of a creator, from when it started until it was last seen live
+#[derive(Debug, Serialize)]
+pub struct StreamSession {
+    pub service: StreamingService,
+    pub creator_id: String,
+    pub href: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub started_at: OffsetDateTime,
+    /// Missing while the stream is still live
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub ended_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_seen_at: OffsetDateTime,
+    /// Every title the stream had, in the order they were seen
+    pub titles: Vec<String>,
+    pub campaign: bool,
+    pub peak_viewers: Option<u32>,
+    /// Averaged over the samples where viewers were visible
+    pub average_viewers: Option<u32>,
+    /// The number of refreshes the stream was seen in
+    pub samples: u32,
+}
+
+/// Record the streams that are live right now, ending any open session that is not
+pub fn record(
+    connection: &mut Connection,
+    now: OffsetDateTime,
+    samples: &[LiveSample],
+) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+
+    {
+        let mut upsert_session = transaction.prepare_cached(
+            "
+            INSERT INTO stream_sessions (
+                service, creator_id, href, started_at, last_seen_at, campaign,
+                peak_viewers, viewer_total, viewer_samples, samples
+            )
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, coalesce(?7, 0), ?7 IS NOT NULL, 1)
+            ON CONFLICT (service, creator_id, href, started_at) DO UPDATE SET
+                ended_at = NULL,
+                last_seen_at = excluded.last_seen_at,
+                campaign = campaign OR excluded.campaign,
+                peak_viewers = max(
+                    coalesce(peak_viewers, excluded.peak_viewers),
+                    coalesce(excluded.peak_viewers, peak_viewers)
+                ),
+                viewer_total = viewer_total + excluded.viewer_total,
+                viewer_samples = viewer_samples + excluded.viewer_samples,
+                samples = samples + 1
+            RETURNING id
+            ",
+        )?;
+        let mut insert_title = transaction.prepare_cached(
+            "INSERT OR IGNORE INTO stream_session_titles (session_id, title) VALUES (?1, ?2)",
+        )?;
+
+        for sample in samples {
+            let session_id: i64 = upsert_session.query_row(
+                params![
+                    sample.creator.service.to_string(),
+                    sample.creator.id,
+                    sample.href,
+                    sample.started_at.unix_timestamp(),
+                    now.unix_timestamp(),
+                    sample.campaign,
+                    sample.viewers,
+                ],
+                |row| row.get(0),
+            )?;
+
+            insert_title.execute(params![session_id, sample.title])?;
+        }
+    }
+
+    // Every session that was not just seen has ended
+    transaction.execute(
+        "UPDATE stream_sessions SET ended_at = last_seen_at WHERE ended_at IS NULL AND last_seen_at < ?1",
+        params![now.unix_timestamp()],
+    )?;
+
+    transaction.commit()
+}
+
+/// The most recent sessions, optionally of a single creator
+pub fn sessions(
+    connection: &Connection,
+    creator: Option<&CreatorKey>,
+    limit: u32,
+) -> rusqlite::Result<Vec<StreamSession>> {
+    let mut select_sessions = connection.prepare_cached(
+        "
+        SELECT
+            id, service, creator_id, href, started_at, ended_at, last_seen_at, campaign,
+            peak_viewers, viewer_total / nullif(viewer_samples, 0), samples
+        FROM stream_sessions
+        WHERE ?1 IS NULL OR (service = ?1 AND creator_id = ?2)
+        ORDER BY started_at DESC
+        LIMIT ?3
+        ",
+    )?;
+    let mut select_titles = connection.prepare_cached(
+        "SELECT title FROM stream_session_titles WHERE session_id = ?1 ORDER BY rowid",
+    )?;
+
+    let rows = select_sessions
+        .query_map(
+            params![
+                creator.map(|creator| creator.service.to_string()),
+                creator.map(|creator| creator.id.as_str()),
+                limit,
+            ],
+            |row| Ok((row.get::<_, i64>(0)?, session_from_row(row)?)),
+        )?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    rows.into_iter()
+        .map(|(session_id, mut session)| {
+            session.titles = select_titles
+                .query_map(params![session_id], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?;
+
+            Ok(session)
+        })
+        .collect()
+}
+
+fn session_from_row(row: &Row) -> rusqlite::Result<StreamSession> {
+    Ok(StreamSession {
+        service: parsed(row, 1)?,
+        creator_id: row.get(2)?,
+        href: row.get(3)?,
+        started_at: timestamp(row, 4)?,
+        ended_at: row
+            .get::<_, Option<i64>>(5)?
+            .map(|_| timestamp(row, 5))
+            .transpose()?,
+        last_seen_at: timestamp(row, 6)?,
+        titles: Vec::new(),
+        campaign: row.get(7)?,
+        peak_viewers: row.get(8)?,
+        average_viewers: row.get(9)?,
+        samples: row.get(10)?,
+    })
+}
+
+/// Time each creator spent streaming for the campaign since it started
+pub fn streamed_time(
+    connection: &Connection,
+    since: OffsetDateTime,
+) -> rusqlite::Result<HashMap<CreatorKey, Duration>> {
+    let mut select_streamed = connection.prepare_cached(
+        "
+        SELECT service, creator_id, sum(coalesce(ended_at, last_seen_at) - max(started_at, ?1))
+        FROM stream_sessions
+        WHERE campaign AND coalesce(ended_at, last_seen_at) > ?1
+        GROUP BY service, creator_id
+        ",
+    )?;
+
+    let rows = select_streamed.query_map(params![since.unix_timestamp()], |row| {
+        Ok((
+            CreatorKey {
+                service: parsed(row, 0)?,
+                id: row.get(1)?,
+            },
+            Duration::seconds(row.get(2)?),
+        ))
+    })?;
+
+    rows.collect()
+}
@@ -0,0 +1,77 @@
+use std::{io::ErrorKind, path::Path, sync::Arc};
+
+use color_eyre::eyre::Context;
+use tokio::{fs, io::AsyncWriteExt, sync::watch};
+use tracing::{error, info, trace, warn};
+
+use crate::watcher::{WatcherData, WatcherDataReceive};
+
+/// Load the last published data, flagged as restored until live data replaces it
+#[tracing::instrument]
+pub async fn load(path: &Path) -> Option<WatcherData> {
+    let snapshot = match fs::read(path).await {
+        Ok(snapshot) => snapshot,
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            info!("no snapshot to restore");
+            return None;
+        }
+        Err(error) => {
+            warn!(?error, "failed to read snapshot");
+            return None;
+        }
+    };
+
+    // The snapshot is only a cache, so an incompatible one is thrown away
+    match serde_json::from_slice::<WatcherData>(&snapshot) {
+        Ok(data) => {
+            info!(updated = %data.updated, "restored snapshot");
+
+            Some(WatcherData {
+                restored: true,
+                ..data
+            })
+        }
+        Err(error) => {
+            warn!(?error, "discarding incompatible snapshot");
+            None
+        }
+    }
+}
+
+/// Persist every live update, so restarts can serve data immediately
+#[tracing::instrument(skip(watcher_data))]
+pub async fn snapshot_writer(path: &Path, mut watcher_data: watch::Receiver<WatcherDataReceive>) {
+    while watcher_data.changed().await.is_ok() {
+        let data = match watcher_data.borrow().as_ref() {
+            // No point writing back what was just restored
+            Some(data) if !data.restored => Arc::clone(data),
+            _ => continue,
+        };
+
+        match write(path, &data).await {
+            Ok(()) => trace!("wrote snapshot"),
+            Err(error) => error!(?error, "failed to write snapshot"),
+        }
+    }
+}
+
+/// Write to a temporary file and rename it over the snapshot, so a crash never leaves
+/// a partially written snapshot behind
+async fn write(path: &Path, data: &WatcherData) -> color_eyre::Result<()> {
+    let snapshot = serde_json::to_vec(data).wrap_err("failed to serialize snapshot")?;
+    let temporary_path = path.with_extension("tmp");
+
+    let mut file = fs::File::create(&temporary_path)
+        .await
+        .wrap_err("failed to create temporary snapshot")?;
+    file.write_all(&snapshot)
+        .await
+        .wrap_err("failed to write temporary snapshot")?;
+    file.sync_all()
+        .await
+        .wrap_err("failed to sync temporary snapshot")?;
+
+    fs::rename(&temporary_path, path)
+        .await
+        .wrap_err("failed to replace snapshot")
+}
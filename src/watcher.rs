@@ -4,19 +4,26 @@ use color_eyre::eyre::Context;
 use futures::FutureExt;
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
-use tokio::sync::watch;
-use tracing::{error, trace};
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, error, trace, warn};
+use twitch_api::types::UserIdRef;
 
 use crate::{
     config::Config,
+    events::ChangeEmitter,
     metrics::types::{LiveCreatorsMetric, StreamingServiceMetricKey, YoutubeQuotaUsageMetric},
-    model::{Campaign, Creator},
+    model::{
+        lineup::LineupSlot, person::Person, Campaign, Creator, FundraisingTotals, StreamingService,
+    },
 };
 
 use self::{
-    tiltify::TiltifyWatcher,
-    twitch::{TwitchEnvironment, TwitchLiveWatcher},
-    youtube::YoutubeEnvironment,
+    tiltify::{TiltifyWatcher, WebhookEvent},
+    twitch::{eventsub::EventSubEvent, TwitchEnvironment, TwitchLiveWatcher},
+    youtube::{
+        api::{ChannelId, YoutubeHandleRef},
+        YoutubeEnvironment,
+    },
 };
 
 pub mod tiltify;
@@ -37,12 +44,32 @@ pub struct WatcherEnvironment {
 
 pub type WatcherDataReceive = Option<Arc<WatcherData>>;
 
-#[derive(Debug, Serialize, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct WatcherData {
+    /// When the data last changed
     #[serde(with = "time::serde::rfc3339")]
     pub updated: OffsetDateTime,
-    pub creators: Box<[Creator]>,
+    pub creators: Arc<[Creator]>,
+    /// The creators grouped by the person behind them, referencing entries in `creators`
+    pub people: Box<[Person]>,
     pub tiltify: Arc<Campaign>,
+    pub totals: FundraisingTotals,
+    pub lineup: Box<[LineupSlot]>,
+    /// Whether the data was restored from a snapshot, and may be stale
+    #[serde(default)]
+    pub restored: bool,
+}
+
+impl WatcherData {
+    /// Compare everything except the time of the update
+    fn same_content(&self, other: &Self) -> bool {
+        self.restored == other.restored
+            && self.creators == other.creators
+            && self.people == other.people
+            && self.tiltify == other.tiltify
+            && self.totals == other.totals
+            && self.lineup == other.lineup
+    }
 }
 
 pub async fn live_watcher(
@@ -50,6 +77,9 @@ pub async fn live_watcher(
     environment: WatcherEnvironment,
     config: &Config,
     sender: watch::Sender<WatcherDataReceive>,
+    mut changes: ChangeEmitter,
+    mut tiltify_events: mpsc::Receiver<WebhookEvent>,
+    mut youtube_notifications: mpsc::Receiver<ChannelId>,
     live_creators: LiveCreatorsMetric,
     youtube_quota_usage: YoutubeQuotaUsageMetric,
 ) {
@@ -66,11 +96,114 @@ pub async fn live_watcher(
     )
     .await;
 
+    let mut twitch_events = twitch_live_watcher.start_eventsub();
+
     let mut interval = tokio::time::interval(config.refresh_period);
     interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
     loop {
-        interval.tick().await;
+        tokio::select! {
+            _ = interval.tick() => {}
+            Some(event) = tiltify_events.recv() => {
+                let tiltify = match tiltify_watcher.apply_webhook_event(event) {
+                    Ok(Some(tiltify)) => tiltify,
+                    Ok(None) => continue,
+                    Err(error) => {
+                        error!(?error, "failed to apply tiltify webhook event");
+                        continue;
+                    }
+                };
+
+                // Only patch live data, the next refresh will populate everything else
+                let creators = match sender.borrow().as_deref() {
+                    Some(data) if !data.restored => data.creators.to_vec(),
+                    _ => continue,
+                };
+
+                trace!("publishing tiltify webhook update");
+
+                publish(&sender, &mut changes, config, &live_creators, creators, tiltify);
+
+                continue;
+            }
+            Some(event) = twitch_events.recv() => {
+                // Only patch live data, the next refresh will populate everything else
+                let (mut creators, tiltify) = match sender.borrow().as_deref() {
+                    Some(data) if !data.restored => (data.creators.to_vec(), data.tiltify.clone()),
+                    _ => continue,
+                };
+
+                if let EventSubEvent::StreamOnline { user_id, .. } = &event {
+                    // Fetch the full stream details, since the event only has the start time
+                    let stream = twitch_live_watcher
+                        .get_creator_stream(UserIdRef::from_str(user_id))
+                        .await;
+
+                    match stream {
+                        Ok(Some(stream)) => {
+                            if let Some(creator) = creators
+                                .iter_mut()
+                                .find(|creator| creator.is(StreamingService::Twitch, user_id))
+                            {
+                                creator.streams = vec![stream];
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(error) => {
+                            error!(?error, "failed to update online twitch creator");
+                        }
+                    }
+                }
+
+                apply_eventsub_event(&mut creators, event);
+
+                trace!("publishing twitch eventsub update");
+
+                publish(&sender, &mut changes, config, &live_creators, creators, tiltify);
+
+                continue;
+            }
+            Some(channel_id) = youtube_notifications.recv() => {
+                // Only patch live data, the next refresh will populate everything else
+                let (mut creators, tiltify) = match sender.borrow().as_deref() {
+                    Some(data) if !data.restored => (data.creators.to_vec(), data.tiltify.clone()),
+                    _ => continue,
+                };
+
+                let Some(creator) = creators
+                    .iter_mut()
+                    .find(|creator| creator.is(StreamingService::Youtube, channel_id.as_str()))
+                else {
+                    warn!(%channel_id, "websub notification for an unknown creator");
+                    continue;
+                };
+
+                match youtube::get_creator_stream(
+                    &http_client,
+                    YoutubeHandleRef::from_str(&creator.handle),
+                    &channel_id,
+                    &environment.youtube,
+                    &youtube_quota_usage,
+                )
+                .await
+                {
+                    Ok(broadcasts) => {
+                        creator.streams = broadcasts.live;
+                        creator.upcoming = broadcasts.upcoming;
+                    }
+                    Err(error) => {
+                        error!(?error, "failed to update notified youtube creator");
+                        continue;
+                    }
+                }
+
+                trace!("publishing youtube websub update");
+
+                publish(&sender, &mut changes, config, &live_creators, creators, tiltify);
+
+                continue;
+            }
+        }
 
         let result = tokio::try_join!(
             youtube::get_creators(
@@ -96,30 +229,137 @@ pub async fn live_watcher(
             }
         };
 
-        let mut creators = twitch
+        let creators = twitch
             .into_iter()
             .chain(youtube.into_iter())
-            .collect::<Box<[Creator]>>();
-
-        creators.sort();
-
-        for creator in creators.iter() {
-            live_creators
-                .get_or_create(&StreamingServiceMetricKey {
-                    service: creator.service,
-                    username: creator.handle.clone(),
-                    id: creator.id.clone(),
-                })
-                .set(creator.stream.is_some().into());
-        }
+            .collect::<Vec<Creator>>();
 
-        // TODO: unmerge creators and tiltify?
-        sender.send_replace(Some(Arc::new(WatcherData {
-            updated: OffsetDateTime::now_utc(),
+        publish(
+            &sender,
+            &mut changes,
+            config,
+            &live_creators,
             creators,
             tiltify,
-        })));
+        );
 
         trace!(?config.refresh_period, "waiting for next refresh");
     }
 }
+
+fn publish(
+    sender: &watch::Sender<WatcherDataReceive>,
+    changes: &mut ChangeEmitter,
+    config: &Config,
+    live_creators: &LiveCreatorsMetric,
+    mut creators: Vec<Creator>,
+    tiltify: Arc<Campaign>,
+) {
+    for creator in &mut creators {
+        creator.pin_campaign_streams(config.campaign.stream_keywords);
+    }
+
+    creators.sort_by(Creator::display_order);
+
+    let creators = Arc::<[Creator]>::from(creators);
+
+    for creator in creators.iter() {
+        live_creators
+            .get_or_create(&StreamingServiceMetricKey {
+                service: creator.service,
+                username: creator.handle.clone(),
+                id: creator.id.clone(),
+            })
+            .set(creator.is_live().into());
+    }
+
+    let updated = OffsetDateTime::now_utc();
+
+    let lineup = config
+        .lineup
+        .iter()
+        .map(|slot| LineupSlot::resolve(slot, &creators))
+        .collect();
+
+    let people = Person::group(config.people, &creators).into();
+
+    // TODO: unmerge creators and tiltify?
+    let data = Arc::new(WatcherData {
+        updated,
+        totals: FundraisingTotals::new(&tiltify, &creators),
+        lineup,
+        people,
+        creators,
+        tiltify,
+        restored: false,
+    });
+
+    let mut previous = None;
+
+    // Only wake up the receivers, and re-render every live view, if something actually changed
+    let modified = sender.send_if_modified(|current| {
+        if current
+            .as_deref()
+            .map_or(false, |current| current.same_content(&data))
+        {
+            return false;
+        }
+
+        previous = current.replace(Arc::clone(&data));
+
+        true
+    });
+
+    if !modified {
+        trace!("watcher data unchanged, skipping publish");
+        return;
+    }
+
+    // Without anything to compare against, everything would look like it just changed
+    if let Some(previous) = previous {
+        changes.emit(&previous, &data, &config.campaign);
+    }
+}
+
+/// Patch the twitch creators with a change reported by EventSub
+fn apply_eventsub_event(creators: &mut [Creator], event: EventSubEvent) {
+    let user_id = match &event {
+        EventSubEvent::StreamOnline { user_id, .. }
+        | EventSubEvent::StreamOffline { user_id }
+        | EventSubEvent::ChannelUpdate { user_id, .. } => user_id,
+    };
+
+    let Some(creator) = creators
+        .iter_mut()
+        .find(|creator| creator.is(StreamingService::Twitch, user_id))
+    else {
+        warn!(%user_id, "eventsub event for an unknown creator");
+        return;
+    };
+
+    match event {
+        EventSubEvent::StreamOnline { .. } => {
+            // Helix can lag behind EventSub, nothing is known about the stream until it catches
+            // up, and a made up stream would be announced as is
+            if !creator.is_live() {
+                debug!(creator = %creator.key(), "stream not yet visible through helix");
+            }
+        }
+        EventSubEvent::StreamOffline { .. } => {
+            creator.streams.clear();
+        }
+        EventSubEvent::ChannelUpdate {
+            title,
+            category,
+            language,
+            ..
+        } => {
+            // Twitch channels only ever have a single stream
+            if let Some(stream) = creator.streams.first_mut() {
+                stream.title = title;
+                stream.category = category;
+                stream.language = language;
+            }
+        }
+    }
+}
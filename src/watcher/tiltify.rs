@@ -1,4 +1,9 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use axum::http::HeaderValue;
 use color_eyre::{
@@ -6,10 +11,24 @@ use color_eyre::{
     Help,
 };
 use hyper::{header, StatusCode};
-use serde::Deserialize;
-use tracing::trace;
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer};
+use serde_json::value::RawValue;
+use time::OffsetDateTime;
+use tracing::{debug, trace, warn};
+
+use crate::{
+    config::CampaignConfig,
+    model::{
+        javascript_unix_timestamp,
+        money::{CurrencyCode, CurrencyCodeRef, Money},
+        Campaign, TiltifyAvatar, TiltifyTeam, TiltifyUser,
+    },
+};
 
-use crate::{config::CampaignConfig, model::Campaign};
+/// How long a donation is remembered since it was last seen, well past the point tiltify
+/// stops redelivering its events
+const DONATION_MEMORY: Duration = Duration::from_secs(60 * 60);
 
 #[derive(Debug, Deserialize)]
 struct Meta {
@@ -22,13 +41,149 @@ struct TiltifyJson<D> {
     data: D,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TiltifyCampaign {
+    id: u32,
+    name: String,
+    slug: String,
+    #[serde(deserialize_with = "javascript_unix_timestamp::deserialize")]
+    starts_at: OffsetDateTime,
+    #[serde(deserialize_with = "javascript_unix_timestamp::option::deserialize")]
+    ends_at: Option<OffsetDateTime>,
+    description: String,
+    avatar: TiltifyAvatar,
+    cause_id: u32,
+
+    // Not always provided by the v3 api
+    #[serde(default)]
+    currency: Option<CurrencyCode>,
+
+    #[serde(deserialize_with = "exact_decimal")]
+    fundraiser_goal_amount: Decimal,
+    #[serde(deserialize_with = "exact_decimal")]
+    original_fundraiser_goal: Decimal,
+    #[serde(deserialize_with = "exact_decimal")]
+    amount_raised: Decimal,
+    #[serde(deserialize_with = "exact_decimal")]
+    supporting_amount_raised: Decimal,
+    #[serde(deserialize_with = "exact_decimal")]
+    total_amount_raised: Decimal,
+
+    supportable: bool,
+
+    user: TiltifyUser,
+    team: TiltifyTeam,
+}
+
+/// The v3 api sends amounts as JSON numbers, which would otherwise be read through an `f64`
+fn exact_decimal<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = Box::<RawValue>::deserialize(deserializer)?;
+    let raw = raw.get();
+    let number = raw
+        .strip_prefix('"')
+        .and_then(|raw| raw.strip_suffix('"'))
+        .unwrap_or(raw);
+
+    Decimal::from_str_exact(number).map_err(de::Error::custom)
+}
+
+impl TiltifyCampaign {
+    fn into_campaign(self, default_currency: &CurrencyCodeRef) -> Campaign {
+        let currency = self.currency.unwrap_or_else(|| default_currency.to_owned());
+        let money = |amount| Money::new(amount, currency.clone());
+
+        Campaign {
+            id: self.id,
+            name: self.name,
+            slug: self.slug,
+            starts_at: self.starts_at,
+            ends_at: self.ends_at,
+            description: self.description,
+            avatar: self.avatar,
+            cause_id: self.cause_id,
+            fundraiser_goal_amount: money(self.fundraiser_goal_amount),
+            original_fundraiser_goal: money(self.original_fundraiser_goal),
+            amount_raised: money(self.amount_raised),
+            supporting_amount_raised: money(self.supporting_amount_raised),
+            total_amount_raised: money(self.total_amount_raised),
+            supportable: self.supportable,
+            user: self.user,
+            team: self.team,
+        }
+    }
+}
+
+#[aliri_braid::braid(serde, display = "omit", debug = "omit")]
+pub struct WebhookSecret;
+
+impl Debug for WebhookSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "*****")
+    }
+}
+
+/// A webhook event delivered by Tiltify
+///
+/// <https://developers.tiltify.com/docs/webhooks>
+#[derive(Debug, Deserialize)]
+pub struct WebhookEvent {
+    pub meta: WebhookMeta,
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookMeta {
+    pub id: String,
+    pub event_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookAmount {
+    currency: CurrencyCode,
+    #[serde(with = "rust_decimal::serde::str")]
+    value: Decimal,
+}
+
+impl From<WebhookAmount> for Money {
+    fn from(amount: WebhookAmount) -> Self {
+        Money::new(amount.value, amount.currency)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookDonation {
+    id: String,
+    campaign_id: String,
+    amount: WebhookAmount,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookCampaign {
+    id: String,
+    legacy_id: u32,
+    amount_raised: WebhookAmount,
+    supporting_amount_raised: Option<WebhookAmount>,
+    total_amount_raised: WebhookAmount,
+    goal: WebhookAmount,
+}
+
 pub struct TiltifyWatcher {
     http_client: reqwest::Client,
     campaign: CampaignConfig,
     api_key: String,
 
     // ETag based cache
-    cache: Option<(HeaderValue, Arc<Campaign>)>,
+    etag: Option<HeaderValue>,
+    cache: Option<Arc<Campaign>>,
+
+    // State applied from webhooks, donations are remembered across polls, by when they were
+    // last seen, as redelivered events would otherwise be added on top of a polled total
+    webhook_campaign_id: Option<String>,
+    seen_donations: HashMap<String, Instant>,
 }
 
 impl TiltifyWatcher {
@@ -37,7 +192,10 @@ impl TiltifyWatcher {
             http_client,
             campaign,
             api_key,
+            etag: None,
             cache: None,
+            webhook_campaign_id: None,
+            seen_donations: HashMap::new(),
         }
     }
 
@@ -56,7 +214,7 @@ impl TiltifyWatcher {
 
         // Assuming `cache-control: must-revalidate, private, max-age=0`
         // as that is what the endpoint headers stated at time of development
-        if let Some((etag, _)) = self.cache.as_ref() {
+        if let Some(etag) = self.etag.as_ref() {
             request
                 .headers_mut()
                 .append(header::IF_NONE_MATCH, etag.clone());
@@ -81,16 +239,22 @@ impl TiltifyWatcher {
                 .await
                 .wrap_err("unable to receive text response from tiltify api")?;
 
-            let json: TiltifyJson<Campaign> = serde_json::from_str(&response)
+            let json: TiltifyJson<TiltifyCampaign> = serde_json::from_str(&response)
                 .wrap_err("incompatible json received from tiltify api")
                 .with_note(|| response)?;
 
             trace!(?etag, "caching new campaign");
 
-            self.cache = Some((etag, Arc::new(json.data)));
+            self.etag = Some(etag);
+            self.cache = Some(Arc::new(json.data.into_campaign(self.campaign.currency)));
+
+            // The polled total includes every donation so far, so only recent ones can
+            // still be redelivered
+            self.seen_donations
+                .retain(|_, seen| seen.elapsed() < DONATION_MEMORY);
         }
 
-        let (_, cached) = self
+        let cached = self
             .cache
             .as_ref()
             .cloned()
@@ -98,4 +262,80 @@ impl TiltifyWatcher {
 
         Ok(cached)
     }
+
+    /// Apply a webhook event to the cached campaign, returning the updated campaign
+    /// if the event changed it
+    #[tracing::instrument(skip(self, event), fields(event_type = %event.meta.event_type, id = %event.meta.id))]
+    pub fn apply_webhook_event(
+        &mut self,
+        event: WebhookEvent,
+    ) -> color_eyre::Result<Option<Arc<Campaign>>> {
+        let Some(cached) = self.cache.as_mut() else {
+            debug!("no campaign has been polled yet, ignoring webhook event");
+            return Ok(None);
+        };
+
+        match event.meta.event_type.as_str() {
+            "public:direct:fact_updated" => {
+                let campaign: WebhookCampaign = serde_json::from_value(event.data)
+                    .wrap_err("incompatible campaign received from tiltify webhook")?;
+
+                if campaign.legacy_id != cached.id {
+                    warn!(campaign.legacy_id, "webhook event for an unknown campaign");
+                    return Ok(None);
+                }
+
+                self.webhook_campaign_id = Some(campaign.id);
+
+                let cached = Arc::make_mut(cached);
+                cached.amount_raised = campaign.amount_raised.into();
+                cached.total_amount_raised = campaign.total_amount_raised.into();
+                cached.fundraiser_goal_amount = campaign.goal.into();
+                if let Some(supporting_amount_raised) = campaign.supporting_amount_raised {
+                    cached.supporting_amount_raised = supporting_amount_raised.into();
+                }
+            }
+            "public:direct:donation_updated" => {
+                let donation: WebhookDonation = serde_json::from_value(event.data)
+                    .wrap_err("incompatible donation received from tiltify webhook")?;
+
+                // Donation events only carry the new campaign id, so they can only
+                // be matched once a campaign event has revealed it
+                if self.webhook_campaign_id.as_deref() != Some(donation.campaign_id.as_str()) {
+                    debug!(%donation.campaign_id, "donation for an unmatched campaign");
+                    return Ok(None);
+                }
+
+                // Donations can be updated multiple times, only count them once
+                if self
+                    .seen_donations
+                    .insert(donation.id, Instant::now())
+                    .is_some()
+                {
+                    return Ok(None);
+                }
+
+                let amount = Money::from(donation.amount);
+
+                let cached = Arc::make_mut(cached);
+                cached.amount_raised = cached
+                    .amount_raised
+                    .checked_add(&amount)
+                    .wrap_err("donation is in a different currency to the campaign")?;
+                cached.total_amount_raised = cached
+                    .total_amount_raised
+                    .checked_add(&amount)
+                    .wrap_err("donation is in a different currency to the campaign")?;
+            }
+            event_type => {
+                debug!(event_type, "ignoring unsupported webhook event");
+                return Ok(None);
+            }
+        }
+
+        // Force the next poll to fetch a fresh copy to reconcile against
+        self.etag = None;
+
+        Ok(self.cache.clone())
+    }
 }
@@ -1,22 +1,43 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use color_eyre::eyre::{Context, ContextCompat};
-use futures::{stream::FuturesUnordered, FutureExt, TryFutureExt, TryStreamExt};
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt, TryFutureExt, TryStreamExt};
+use once_cell::sync::Lazy;
+use reqwest::Url;
+use rust_decimal::Decimal;
 use serde::Deserialize;
 use time::{format_description::well_known, OffsetDateTime};
-use tokio::pin;
-use tracing::{info, trace};
+use tokio::{
+    pin,
+    sync::{mpsc, Mutex},
+};
+use tracing::{debug, info, trace, warn};
 use twitch_api::{
     helix::{
-        streams::GetStreamsRequest,
+        charity::{CharityAmount, GetCharityCampaignRequest},
+        schedule::GetChannelStreamScheduleRequest,
+        streams::{GetStreamsRequest, Stream},
         users::{GetUsersRequest, User},
         ClientRequestError,
     },
-    twitch_oauth2::{AppAccessToken, ClientId, ClientSecret, TwitchToken},
-    types::{Nickname, NicknameRef},
+    twitch_oauth2::{
+        AppAccessToken, ClientId, ClientSecret, RefreshToken, Scope, TwitchToken, UserToken,
+    },
+    types::{Nickname, NicknameRef, UserId, UserIdRef},
 };
 
-use crate::model::{Creator, LiveStreamDetails, StreamingService};
+use crate::model::{
+    money::Money, ChannelStats, CharityFundraiser, Creator, LiveStreamDetails, ScheduledStream,
+    StreamingService,
+};
+
+use self::eventsub::{EventSubClient, EventSubEvent};
+
+pub mod eventsub;
+
+/// Size of the stream thumbnails shown on creator cards
+const THUMBNAIL_WIDTH: u32 = 440;
+const THUMBNAIL_HEIGHT: u32 = 248;
 
 #[derive(Deserialize, Debug)]
 pub struct TwitchEnvironment {
@@ -24,12 +45,32 @@ pub struct TwitchEnvironment {
     client_id: ClientId,
     #[serde(rename = "twitch_client_secret")]
     client_secret: ClientSecret,
+    /// Comma separated refresh tokens of broadcasters who have authorized
+    /// `channel:read:charity` to opt in to charity fundraiser tracking
+    #[serde(rename = "twitch_charity_refresh_tokens")]
+    charity_refresh_tokens: Option<String>,
+    /// Refresh token of any user, EventSub websockets and follower counts cannot be used with
+    /// an app access token. Live status updates rely on polling alone if not set
+    #[serde(rename = "twitch_eventsub_refresh_token")]
+    eventsub_refresh_token: Option<String>,
+    #[serde(rename = "twitch_eventsub_websocket_url")]
+    eventsub_websocket_url: Option<Url>,
+    #[serde(rename = "twitch_eventsub_subscriptions_url")]
+    eventsub_subscriptions_url: Option<Url>,
 }
 
+/// A user token used from several places, so it is only refreshed once when it expires
+type SharedUserToken = Arc<Mutex<UserToken>>;
+
 pub struct TwitchLiveWatcher {
     helix_client: twitch_api::HelixClient<'static, reqwest::Client>,
+    http_client: reqwest::Client,
     environment: TwitchEnvironment,
     token: AppAccessToken,
+    charity_tokens: Vec<UserToken>,
+    /// Token used to read follower counts, shared with EventSub
+    followers_token: Option<SharedUserToken>,
+    eventsub: Option<EventSubClient>,
     creators_names: &'static [&'static NicknameRef],
 }
 
@@ -40,7 +81,7 @@ impl TwitchLiveWatcher {
         environment: TwitchEnvironment,
         creators_names: &'static [&'static NicknameRef],
     ) -> Self {
-        let helix_client = twitch_api::HelixClient::with_client(http_client);
+        let helix_client = twitch_api::HelixClient::with_client(http_client.clone());
 
         let token = AppAccessToken::get_app_access_token(
             &helix_client,
@@ -55,56 +96,156 @@ impl TwitchLiveWatcher {
 
         info!(?expires_at, "acquired access token");
 
+        let mut charity_tokens = Vec::new();
+
+        for refresh_token in environment
+            .charity_refresh_tokens
+            .iter()
+            .flat_map(|tokens| tokens.split(','))
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+        {
+            let token = match UserToken::from_refresh_token(
+                &helix_client,
+                RefreshToken::new(refresh_token.to_owned()),
+                environment.client_id.clone(),
+                environment.client_secret.clone(),
+            )
+            .await
+            {
+                Ok(token) => token,
+                Err(error) => {
+                    warn!(?error, "failed to authorize charity broadcaster, skipping");
+                    continue;
+                }
+            };
+
+            if !token.scopes().contains(&Scope::ChannelReadCharity) {
+                warn!(login = %token.login, "charity broadcaster token is missing the channel:read:charity scope, skipping");
+                continue;
+            }
+
+            info!(login = %token.login, "authorized charity broadcaster");
+
+            charity_tokens.push(token);
+        }
+
+        let eventsub = match &environment.eventsub_refresh_token {
+            Some(refresh_token) => {
+                match UserToken::from_refresh_token(
+                    &helix_client,
+                    RefreshToken::new(refresh_token.clone()),
+                    environment.client_id.clone(),
+                    environment.client_secret.clone(),
+                )
+                .await
+                {
+                    Ok(token) => Some(EventSubClient {
+                        helix_client: twitch_api::HelixClient::with_client(http_client.clone()),
+                        http_client: http_client.clone(),
+                        client_id: environment.client_id.clone(),
+                        token: Arc::new(Mutex::new(token)),
+                        websocket_url: environment.eventsub_websocket_url.clone().unwrap_or_else(
+                            || {
+                                Url::parse("wss://eventsub.wss.twitch.tv/ws")
+                                    .expect("url should be valid")
+                            },
+                        ),
+                        subscriptions_url: environment
+                            .eventsub_subscriptions_url
+                            .clone()
+                            .unwrap_or_else(|| {
+                                Url::parse("https://api.twitch.tv/helix/eventsub/subscriptions")
+                                    .expect("url should be valid")
+                            }),
+                        creators_names,
+                    }),
+                    Err(error) => {
+                        warn!(
+                            ?error,
+                            "failed to authorize eventsub user, relying on polling"
+                        );
+                        None
+                    }
+                }
+            }
+            None => {
+                info!("no eventsub refresh token provided, relying on polling");
+                None
+            }
+        };
+
+        let followers_token = eventsub.as_ref().map(|eventsub| eventsub.token.clone());
+
         TwitchLiveWatcher {
             helix_client,
+            http_client,
             token,
+            charity_tokens,
+            followers_token,
+            eventsub,
             environment,
             creators_names,
         }
     }
 
+    /// Start the EventSub session in the background, if configured.
+    ///
+    /// The returned receiver never yields any events if EventSub is not configured
+    pub fn start_eventsub(&mut self) -> mpsc::Receiver<EventSubEvent> {
+        let (sender, receiver) = mpsc::channel(64);
+
+        if let Some(eventsub) = self.eventsub.take() {
+            tokio::spawn(eventsub.run(sender));
+        }
+
+        receiver
+    }
+
     #[tracing::instrument(skip(self), fields(creators_names = ?self.creators_names))]
     pub async fn get_creators(&mut self) -> color_eyre::Result<Vec<Creator>> {
+        self.refresh_token().await?;
+
         let client = &self.helix_client;
         let creators_names = self.creators_names;
-        let token: &mut AppAccessToken = &mut self.token;
-
-        if token.is_elapsed() {
-            match token.refresh_token(client).await {
-                Ok(()) => {
-                    trace!(expires_in = ?token.expires_in(), "refreshed access token");
-                }
-                Err(error) => {
-                    tracing::warn!(?error, "failed to refresh access token, re-authenticating");
-
-                    *token = AppAccessToken::get_app_access_token(
-                        &self.helix_client,
-                        self.environment.client_id.clone(),
-                        self.environment.client_secret.clone(),
-                        vec![],
-                    )
-                    .await
-                    .wrap_err("failed to re-authenticate")?;
-                }
-            }
-        }
+        let token = &self.token;
 
-        let (users, streams) = tokio::try_join!(
+        let (users, streams, mut charities) = tokio::try_join!(
             get_user_info(client, creators_names, token)
                 .map(|users| users.wrap_err("failed to fetch user info")),
             get_live_statuses(client, creators_names, token)
-                .map(|users| users.wrap_err("failed to fetch live statuses"))
+                .map(|users| users.wrap_err("failed to fetch live statuses")),
+            // A single broadcaster's charity campaign is not worth failing the refresh over
+            get_charity_campaigns(client, &mut self.charity_tokens).map(color_eyre::Result::Ok)
         )?;
 
+        let (mut schedules, mut followers) = tokio::join!(
+            get_schedules(client, &users, token),
+            get_follower_counts(
+                client,
+                &self.http_client,
+                &self.environment.client_id,
+                self.followers_token.as_ref(),
+                &users
+            )
+        );
+
         users
             .into_iter()
             .map(|user| {
                 Ok(Creator {
                     service: StreamingService::Twitch,
+                    charity: charities.remove(&user.id),
+                    upcoming: schedules.remove(&user.id).unwrap_or_default(),
+                    stats: followers.remove(&user.id).map(|followers| ChannelStats {
+                        followers: Some(followers),
+                        subscribers: None,
+                        views: None,
+                    }),
                     id: user.id.take(),
                     display_name: user.display_name.take(),
                     href: format!("https://twitch.tv/{}", user.login),
-                    stream: streams.get(&user.login).cloned(),
+                    streams: streams.get(&user.login).cloned().into_iter().collect(),
                     handle: user.login.take(),
                     icon_url: user
                         .profile_image_url
@@ -114,6 +255,52 @@ impl TwitchLiveWatcher {
             })
             .collect()
     }
+
+    /// Get the live stream of a single creator, without refreshing anything else
+    #[tracing::instrument(skip(self))]
+    pub async fn get_creator_stream(
+        &mut self,
+        user_id: &UserIdRef,
+    ) -> color_eyre::Result<Option<LiveStreamDetails>> {
+        self.refresh_token().await?;
+
+        let user_ids = [user_id];
+        let streams = self
+            .helix_client
+            .req_get(GetStreamsRequest::user_ids(&user_ids[..]), &self.token)
+            .await
+            .wrap_err("failed to fetch live status")?;
+
+        Ok(streams.data.into_iter().next().map(stream_details))
+    }
+
+    async fn refresh_token(&mut self) -> color_eyre::Result<()> {
+        let token = &mut self.token;
+
+        if !token.is_elapsed() {
+            return Ok(());
+        }
+
+        match token.refresh_token(&self.helix_client).await {
+            Ok(()) => {
+                trace!(expires_in = ?token.expires_in(), "refreshed access token");
+            }
+            Err(error) => {
+                tracing::warn!(?error, "failed to refresh access token, re-authenticating");
+
+                *token = AppAccessToken::get_app_access_token(
+                    &self.helix_client,
+                    self.environment.client_id.clone(),
+                    self.environment.client_secret.clone(),
+                    vec![],
+                )
+                .await
+                .wrap_err("failed to re-authenticate")?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 async fn get_user_info(
@@ -154,25 +341,301 @@ async fn get_live_statuses(
     // Read through pagination
     let mut live_streams = Some(live_streams);
     while let Some(previous) = live_streams {
-        all_streams.extend(previous.data.iter().cloned().map(|stream| {
-            let livestream_details = LiveStreamDetails {
-                href: format!("https://twitch.tv/{}", stream.user_login),
-                title: stream.title,
-                start_time: OffsetDateTime::parse(stream.started_at.as_str(), &well_known::Rfc3339)
-                    .expect("stream start time should be a well formed rfc3339 date-time"),
-                viewers: Some(
-                    stream
-                        .viewer_count
-                        .try_into()
-                        .expect("viewer_count should be no larger than a 32 bit integer"),
-                ),
-            };
-
-            (stream.user_login, livestream_details)
-        }));
+        all_streams.extend(
+            previous
+                .data
+                .iter()
+                .cloned()
+                .map(|stream| (stream.user_login.clone(), stream_details(stream))),
+        );
 
         live_streams = previous.get_next(client, token).await?;
     }
 
     Ok(all_streams)
 }
+
+fn stream_details(stream: Stream) -> LiveStreamDetails {
+    LiveStreamDetails {
+        href: format!("https://twitch.tv/{}", stream.user_login),
+        title: stream.title,
+        start_time: OffsetDateTime::parse(stream.started_at.as_str(), &well_known::Rfc3339)
+            .expect("stream start time should be a well formed rfc3339 date-time"),
+        viewers: Some(
+            stream
+                .viewer_count
+                .try_into()
+                .expect("viewer_count should be no larger than a 32 bit integer"),
+        ),
+        // Streams without a category have an empty game name
+        category: Some(stream.game_name).filter(|game_name| !game_name.is_empty()),
+        tags: stream.tags,
+        language: Some(stream.language).filter(|language| !language.is_empty()),
+        mature: stream.is_mature,
+        thumbnail_url: Some(
+            stream
+                .thumbnail_url
+                .replace("{width}", &THUMBNAIL_WIDTH.to_string())
+                .replace("{height}", &THUMBNAIL_HEIGHT.to_string()),
+        ),
+        campaign: false,
+    }
+}
+
+/// Get the upcoming scheduled streams of every user.
+///
+/// Users without a schedule are missing from the returned map
+#[tracing::instrument(skip_all)]
+async fn get_schedules(
+    client: &twitch_api::HelixClient<'static, reqwest::Client>,
+    users: &[User],
+    token: &AppAccessToken,
+) -> HashMap<UserId, Vec<ScheduledStream>> {
+    let futures: FuturesUnordered<_> = users
+        .iter()
+        .map(|user| async move {
+            let schedule = client
+                .req_get(
+                    GetChannelStreamScheduleRequest::broadcaster_id(&user.id).first(5),
+                    token,
+                )
+                .await;
+
+            (user, schedule)
+        })
+        .collect();
+
+    pin!(futures);
+
+    let mut schedules = HashMap::with_capacity(users.len());
+    let now = OffsetDateTime::now_utc();
+
+    while let Some((user, schedule)) = futures.next().await {
+        // Twitch responds with not found if the user has never set up a schedule
+        let schedule = match schedule {
+            Ok(schedule) => schedule.data,
+            Err(error) => {
+                debug!(?error, login = %user.login, "failed to get stream schedule");
+                continue;
+            }
+        };
+
+        let upcoming = schedule
+            .segments
+            .into_iter()
+            .filter(|segment| segment.canceled_until.is_none())
+            .filter_map(|segment| {
+                let parse = |time: &str| OffsetDateTime::parse(time, &well_known::Rfc3339);
+
+                let times = parse(segment.start_time.as_str()).and_then(|start_time| {
+                    let end_time = segment
+                        .end_time
+                        .as_ref()
+                        .map(|end_time| parse(end_time.as_str()))
+                        .transpose()?;
+
+                    Ok((start_time, end_time))
+                });
+
+                // A single bad segment is not worth taking the watcher down over
+                let (start_time, end_time) = match times {
+                    Ok(times) => times,
+                    Err(error) => {
+                        warn!(
+                            ?error,
+                            login = %user.login,
+                            ?segment,
+                            "skipping malformed schedule segment"
+                        );
+                        return None;
+                    }
+                };
+
+                Some(ScheduledStream {
+                    id: segment.id.take(),
+                    href: format!("https://twitch.tv/{}", user.login),
+                    title: segment.title,
+                    start_time,
+                    end_time,
+                })
+            })
+            .filter(|scheduled| scheduled.start_time > now)
+            .collect();
+
+        schedules.insert(user.id.clone(), upcoming);
+    }
+
+    schedules
+}
+
+#[derive(Debug, Deserialize)]
+struct ChannelFollowers {
+    total: u64,
+}
+
+/// Get the follower count of every user.
+///
+/// The endpoint needs a user access token, so this is empty if no token is configured
+#[tracing::instrument(skip_all)]
+async fn get_follower_counts(
+    client: &twitch_api::HelixClient<'static, reqwest::Client>,
+    http_client: &reqwest::Client,
+    client_id: &ClientId,
+    token: Option<&SharedUserToken>,
+    users: &[User],
+) -> HashMap<UserId, u64> {
+    static CHANNEL_FOLLOWERS_URL: Lazy<Url> = Lazy::new(|| {
+        Url::parse("https://api.twitch.tv/helix/channels/followers").expect("url should be valid")
+    });
+
+    let Some(token) = token else {
+        return HashMap::new();
+    };
+
+    let token = match refreshed_user_token(client, token).await {
+        Ok(token) => token,
+        Err(error) => {
+            warn!(?error, "failed to refresh followers access token");
+            return HashMap::new();
+        }
+    };
+    let token = &token;
+
+    let futures: FuturesUnordered<_> = users
+        .iter()
+        .map(|user| async move {
+            let mut url = CHANNEL_FOLLOWERS_URL.clone();
+
+            // Only the total is needed, not the followers themselves
+            url.query_pairs_mut()
+                .append_pair("broadcaster_id", user.id.as_str())
+                .append_pair("first", "1");
+
+            let followers = async {
+                http_client
+                    .get(url)
+                    .header("client-id", client_id.as_str())
+                    .bearer_auth(token.access_token.secret())
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<ChannelFollowers>()
+                    .await
+            }
+            .await;
+
+            (user, followers)
+        })
+        .collect();
+
+    pin!(futures);
+
+    let mut followers = HashMap::with_capacity(users.len());
+
+    while let Some((user, result)) = futures.next().await {
+        match result {
+            Ok(ChannelFollowers { total }) => {
+                followers.insert(user.id.clone(), total);
+            }
+            Err(error) => {
+                debug!(?error, login = %user.login, "failed to get follower count");
+            }
+        }
+    }
+
+    followers
+}
+
+/// Get the active charity campaigns of all broadcasters who have opted in, skipping any
+/// broadcaster whose campaign could not be fetched
+#[tracing::instrument(skip_all)]
+async fn get_charity_campaigns(
+    client: &twitch_api::HelixClient<'static, reqwest::Client>,
+    tokens: &mut [UserToken],
+) -> HashMap<UserId, CharityFundraiser> {
+    let mut charities = HashMap::with_capacity(tokens.len());
+
+    for token in tokens {
+        match get_charity_campaign(client, token).await {
+            Ok(Some(charity)) => {
+                charities.insert(token.user_id.clone(), charity);
+            }
+            Ok(None) => {}
+            Err(error) => {
+                // Most likely the broadcaster revoked access, or their token expired for good
+                warn!(?error, login = %token.login, "failed to get charity campaign");
+            }
+        }
+    }
+
+    charities
+}
+
+async fn get_charity_campaign(
+    client: &twitch_api::HelixClient<'static, reqwest::Client>,
+    token: &mut UserToken,
+) -> color_eyre::Result<Option<CharityFundraiser>> {
+    if token.is_elapsed() {
+        token
+            .refresh_token(client)
+            .await
+            .wrap_err("failed to refresh token")?;
+
+        trace!(login = %token.login, expires_in = ?token.expires_in(), "refreshed charity access token");
+    }
+
+    let campaign = client
+        .req_get(
+            GetCharityCampaignRequest::broadcaster_id(&token.user_id),
+            &*token,
+        )
+        .await
+        .wrap_err("failed to get charity campaign")?
+        .data;
+
+    let Some(campaign) = campaign else {
+        return Ok(None);
+    };
+
+    let current_amount = charity_amount_to_money(campaign.current_amount)?;
+    let target_amount = campaign
+        .target_amount
+        .map(charity_amount_to_money)
+        .transpose()?;
+
+    Ok(Some(CharityFundraiser {
+        charity_name: campaign.charity_name,
+        charity_website: campaign.charity_website,
+        current_amount,
+        target_amount,
+    }))
+}
+
+fn charity_amount_to_money(amount: CharityAmount) -> color_eyre::Result<Money> {
+    let scale = amount
+        .decimal_places
+        .try_into()
+        .wrap_err("invalid charity amount decimal places")?;
+    let value = Decimal::try_new(amount.value, scale).wrap_err("invalid charity amount")?;
+
+    Ok(Money::new(value, amount.currency.into()))
+}
+
+/// Refresh the shared token if it has expired, returning a copy to use for now
+async fn refreshed_user_token(
+    client: &twitch_api::HelixClient<'static, reqwest::Client>,
+    token: &SharedUserToken,
+) -> color_eyre::Result<UserToken> {
+    let mut token = token.lock().await;
+
+    if token.is_elapsed() {
+        token
+            .refresh_token(client)
+            .await
+            .wrap_err("failed to refresh user access token")?;
+
+        trace!(expires_in = ?token.expires_in(), "refreshed user access token");
+    }
+
+    Ok(token.clone())
+}
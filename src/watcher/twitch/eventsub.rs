@@ -0,0 +1,514 @@
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::{bail, Context};
+use futures::{stream::FuturesUnordered, SinkExt, StreamExt, TryStreamExt};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::{format_description::well_known, OffsetDateTime};
+use tokio::{pin, sync::mpsc};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, error, info, trace, warn};
+use twitch_api::{helix::users::GetUsersRequest, twitch_oauth2::ClientId, types::NicknameRef};
+
+use super::{refreshed_user_token, SharedUserToken};
+
+/// Time allowed past the keepalive timeout before a session is considered dead
+const KEEPALIVE_GRACE: Duration = Duration::from_secs(5);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+/// How long a session has to stay up before a failure is treated as a new one, rather than
+/// another in a row
+const STABLE_SESSION: Duration = Duration::from_secs(60);
+
+/// A change to a broadcaster's channel, as reported by EventSub
+#[derive(Debug)]
+pub enum EventSubEvent {
+    StreamOnline {
+        user_id: String,
+        start_time: OffsetDateTime,
+    },
+    StreamOffline {
+        user_id: String,
+    },
+    ChannelUpdate {
+        user_id: String,
+        title: String,
+        category: Option<String>,
+        language: Option<String>,
+    },
+}
+
+pub struct EventSubClient {
+    pub(super) helix_client: twitch_api::HelixClient<'static, reqwest::Client>,
+    pub(super) http_client: reqwest::Client,
+    pub(super) client_id: ClientId,
+    pub(super) token: SharedUserToken,
+    pub(super) websocket_url: Url,
+    pub(super) subscriptions_url: Url,
+    pub(super) creators_names: &'static [&'static NicknameRef],
+}
+
+enum SessionEnd {
+    /// Twitch asked for the session to be moved to a new url
+    Reconnect(Url),
+    Closed,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventSubMessage {
+    metadata: Metadata,
+    payload: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    message_type: String,
+    subscription_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SessionPayload {
+    session: Session,
+}
+
+#[derive(Debug, Deserialize)]
+struct Session {
+    id: String,
+    keepalive_timeout_seconds: Option<u64>,
+    reconnect_url: Option<Url>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NotificationPayload<E> {
+    event: E,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamOnlineEvent {
+    broadcaster_user_id: String,
+    started_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamOfflineEvent {
+    broadcaster_user_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChannelUpdateEvent {
+    broadcaster_user_id: String,
+    title: String,
+    category_name: String,
+    language: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateSubscription<'s> {
+    r#type: &'static str,
+    version: &'static str,
+    condition: serde_json::Value,
+    transport: Transport<'s>,
+}
+
+#[derive(Debug, Serialize)]
+struct Transport<'s> {
+    method: &'static str,
+    session_id: &'s str,
+}
+
+impl EventSubClient {
+    /// Keep an EventSub session open, reconnecting with backoff until the receiver is dropped
+    #[tracing::instrument(skip_all)]
+    pub async fn run(mut self, events: mpsc::Sender<EventSubEvent>) {
+        let mut backoff = MIN_BACKOFF;
+        let mut url = self.websocket_url.clone();
+        let mut subscribe = true;
+
+        while !events.is_closed() {
+            let started = Instant::now();
+            let subscriber = subscribe.then_some(&mut self);
+
+            match session(&url, subscriber, &events).await {
+                Ok(SessionEnd::Reconnect(reconnect_url)) => {
+                    debug!(%reconnect_url, "moving eventsub session");
+
+                    // Subscriptions carry over to the new session
+                    url = reconnect_url;
+                    subscribe = false;
+                    backoff = MIN_BACKOFF;
+
+                    continue;
+                }
+                Ok(SessionEnd::Closed) => {
+                    warn!(
+                        ?backoff,
+                        "eventsub session closed, relying on polling until reconnected"
+                    );
+                }
+                Err(error) => {
+                    error!(
+                        ?error,
+                        ?backoff,
+                        "eventsub session failed, relying on polling until reconnected"
+                    );
+                }
+            }
+
+            url = self.websocket_url.clone();
+            subscribe = true;
+
+            if started.elapsed() >= STABLE_SESSION {
+                backoff = MIN_BACKOFF;
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Subscribe the session to the events of every creator
+    #[tracing::instrument(skip(self))]
+    async fn subscribe(&mut self, session_id: &str) -> color_eyre::Result<()> {
+        let token = refreshed_user_token(&self.helix_client, &self.token)
+            .await
+            .wrap_err("failed to refresh eventsub access token")?;
+        let token = &token;
+
+        let this = &*self;
+
+        let users = this
+            .creators_names
+            .chunks(100)
+            .map(|creators_names| {
+                this.helix_client
+                    .req_get(GetUsersRequest::logins(creators_names), token)
+            })
+            .collect::<FuturesUnordered<_>>()
+            .map_ok(|response| response.data)
+            .try_concat()
+            .await
+            .wrap_err("failed to fetch user ids")?;
+
+        let futures: FuturesUnordered<_> = users
+            .iter()
+            .flat_map(|user| {
+                [
+                    ("stream.online", "1"),
+                    ("stream.offline", "1"),
+                    ("channel.update", "2"),
+                ]
+                .map(|(r#type, version)| CreateSubscription {
+                    r#type,
+                    version,
+                    condition: json!({ "broadcaster_user_id": user.id }),
+                    transport: Transport {
+                        method: "websocket",
+                        session_id,
+                    },
+                })
+            })
+            .map(|subscription| async move {
+                this.http_client
+                    .post(this.subscriptions_url.clone())
+                    .header("client-id", this.client_id.as_str())
+                    .bearer_auth(token.access_token.secret())
+                    .json(&subscription)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .wrap_err_with(|| format!("failed to subscribe to {}", subscription.r#type))
+            })
+            .collect();
+
+        pin!(futures);
+
+        let subscriptions: Vec<_> = futures.try_collect().await?;
+
+        info!(count = subscriptions.len(), "subscribed to eventsub events");
+
+        Ok(())
+    }
+}
+
+/// Run a session until it ends, subscribing to events once it starts if given a subscriber.
+/// Subscriptions carry over when twitch moves a session
+#[tracing::instrument(skip(subscriber, events))]
+async fn session(
+    url: &Url,
+    mut subscriber: Option<&mut EventSubClient>,
+    events: &mpsc::Sender<EventSubEvent>,
+) -> color_eyre::Result<SessionEnd> {
+    let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+        .await
+        .wrap_err("failed to connect to eventsub websocket")?;
+
+    // Twitch sends the real timeout in the welcome message
+    let mut keepalive_timeout = Duration::from_secs(10) + KEEPALIVE_GRACE;
+
+    loop {
+        let message = tokio::time::timeout(keepalive_timeout, socket.next())
+            .await
+            .wrap_err("no message received within the keepalive timeout")?;
+
+        let message = match message {
+            Some(message) => message.wrap_err("failed to receive websocket message")?,
+            None => return Ok(SessionEnd::Closed),
+        };
+
+        let message = match message {
+            Message::Text(message) => message,
+            Message::Ping(payload) => {
+                socket
+                    .send(Message::Pong(payload))
+                    .await
+                    .wrap_err("failed to respond to ping")?;
+
+                continue;
+            }
+            Message::Close(frame) => {
+                debug!(?frame, "eventsub websocket closed");
+
+                return Ok(SessionEnd::Closed);
+            }
+            _ => continue,
+        };
+
+        let message: EventSubMessage =
+            serde_json::from_str(&message).wrap_err("incompatible eventsub message")?;
+
+        match message.metadata.message_type.as_str() {
+            "session_welcome" => {
+                let SessionPayload { session } = serde_json::from_value(message.payload)
+                    .wrap_err("incompatible eventsub welcome")?;
+
+                if let Some(timeout) = session.keepalive_timeout_seconds {
+                    keepalive_timeout = Duration::from_secs(timeout) + KEEPALIVE_GRACE;
+                }
+
+                info!(session_id = %session.id, ?keepalive_timeout, "eventsub session started");
+
+                if let Some(subscriber) = subscriber.as_deref_mut() {
+                    subscriber
+                        .subscribe(&session.id)
+                        .await
+                        .wrap_err("failed to subscribe to eventsub events")?;
+                }
+            }
+            "session_keepalive" => {
+                trace!("eventsub keepalive");
+            }
+            "session_reconnect" => {
+                let SessionPayload { session } = serde_json::from_value(message.payload)
+                    .wrap_err("incompatible eventsub reconnect")?;
+
+                match session.reconnect_url {
+                    Some(reconnect_url) => return Ok(SessionEnd::Reconnect(reconnect_url)),
+                    None => bail!("eventsub reconnect message is missing a reconnect url"),
+                }
+            }
+            "notification" => {
+                let event = parse_notification(
+                    message.metadata.subscription_type.as_deref(),
+                    message.payload,
+                )
+                .wrap_err("incompatible eventsub notification")?;
+
+                if let Some(event) = event {
+                    debug!(?event, "eventsub notification");
+
+                    if events.send(event).await.is_err() {
+                        return Ok(SessionEnd::Closed);
+                    }
+                }
+            }
+            "revocation" => {
+                warn!(subscription = ?message.payload, "eventsub subscription revoked");
+            }
+            message_type => {
+                debug!(message_type, "ignoring unknown eventsub message");
+            }
+        }
+    }
+}
+
+fn parse_notification(
+    subscription_type: Option<&str>,
+    payload: serde_json::Value,
+) -> color_eyre::Result<Option<EventSubEvent>> {
+    Ok(Some(match subscription_type {
+        Some("stream.online") => {
+            let NotificationPayload::<StreamOnlineEvent> { event } =
+                serde_json::from_value(payload)?;
+
+            EventSubEvent::StreamOnline {
+                user_id: event.broadcaster_user_id,
+                start_time: OffsetDateTime::parse(&event.started_at, &well_known::Rfc3339)
+                    .wrap_err("started_at should be a valid RFC3339 date-time")?,
+            }
+        }
+        Some("stream.offline") => {
+            let NotificationPayload::<StreamOfflineEvent> { event } =
+                serde_json::from_value(payload)?;
+
+            EventSubEvent::StreamOffline {
+                user_id: event.broadcaster_user_id,
+            }
+        }
+        Some("channel.update") => {
+            let NotificationPayload::<ChannelUpdateEvent> { event } =
+                serde_json::from_value(payload)?;
+
+            EventSubEvent::ChannelUpdate {
+                user_id: event.broadcaster_user_id,
+                title: event.title,
+                category: Some(event.category_name).filter(|category| !category.is_empty()),
+                language: Some(event.language).filter(|language| !language.is_empty()),
+            }
+        }
+        _ => return Ok(None),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn welcome() -> Value {
+        json!({
+            "metadata": { "message_type": "session_welcome" },
+            "payload": {
+                "session": {
+                    "id": "AQoQexAWVYKSTIu4ec_2VAxyuhAB",
+                    "keepalive_timeout_seconds": 10,
+                    "reconnect_url": null
+                }
+            }
+        })
+    }
+
+    /// A local stand-in for twitch's EventSub websocket, which sends the messages to the first
+    /// connection and then closes it
+    async fn stand_in(messages: Vec<Value>) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("should bind a local port");
+        let address = listener
+            .local_addr()
+            .expect("listener should have an address");
+        let url = Url::parse(&format!("ws://{address}/ws")).expect("url should be valid");
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("session should connect");
+            let mut socket = tokio_tungstenite::accept_async(stream)
+                .await
+                .expect("websocket handshake should succeed");
+
+            for message in messages {
+                socket
+                    .send(Message::Text(message.to_string()))
+                    .await
+                    .expect("message should be sent");
+            }
+
+            // The session may already have hung up
+            let _ = socket.close(None).await;
+        });
+
+        url
+    }
+
+    #[tokio::test]
+    async fn forwards_notifications() {
+        let url = stand_in(vec![
+            welcome(),
+            json!({ "metadata": { "message_type": "session_keepalive" }, "payload": {} }),
+            json!({
+                "metadata": { "message_type": "notification", "subscription_type": "stream.online" },
+                "payload": {
+                    "event": {
+                        "broadcaster_user_id": "1337",
+                        "started_at": "2023-02-18T18:00:00Z"
+                    }
+                }
+            }),
+            json!({
+                "metadata": { "message_type": "notification", "subscription_type": "channel.update" },
+                "payload": {
+                    "event": {
+                        "broadcaster_user_id": "1337",
+                        "title": "Creators for a Cause!",
+                        "category_name": "",
+                        "language": "en"
+                    }
+                }
+            }),
+            json!({
+                "metadata": { "message_type": "notification", "subscription_type": "stream.offline" },
+                "payload": { "event": { "broadcaster_user_id": "1337" } }
+            }),
+        ])
+        .await;
+
+        let (sender, mut receiver) = mpsc::channel(8);
+        let end = session(&url, None, &sender)
+            .await
+            .expect("session should end cleanly");
+
+        assert!(matches!(end, SessionEnd::Closed));
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(EventSubEvent::StreamOnline { user_id, .. }) if user_id == "1337"
+        ));
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(EventSubEvent::ChannelUpdate { title, category: None, language: Some(language), .. })
+                if title == "Creators for a Cause!" && language == "en"
+        ));
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(EventSubEvent::StreamOffline { user_id }) if user_id == "1337"
+        ));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn follows_reconnect() {
+        let url = stand_in(vec![
+            welcome(),
+            json!({
+                "metadata": { "message_type": "session_reconnect" },
+                "payload": {
+                    "session": {
+                        "id": "AQoQexAWVYKSTIu4ec_2VAxyuhAB",
+                        "keepalive_timeout_seconds": null,
+                        "reconnect_url": "wss://eventsub.wss.twitch.tv/ws?id=moved"
+                    }
+                }
+            }),
+        ])
+        .await;
+
+        let (sender, _receiver) = mpsc::channel(8);
+        let end = session(&url, None, &sender)
+            .await
+            .expect("session should end cleanly");
+
+        assert!(matches!(
+            end,
+            SessionEnd::Reconnect(url) if url.as_str() == "wss://eventsub.wss.twitch.tv/ws?id=moved"
+        ));
+    }
+
+    #[tokio::test]
+    async fn fails_on_incompatible_message() {
+        let url = stand_in(vec![json!({ "metadata": {} })]).await;
+
+        let (sender, _receiver) = mpsc::channel(8);
+
+        assert!(session(&url, None, &sender).await.is_err());
+    }
+}
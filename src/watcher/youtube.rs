@@ -1,7 +1,7 @@
 use std::fmt::Debug;
 
 use color_eyre::eyre::Context;
-use futures::{stream::FuturesUnordered, TryStreamExt};
+use futures::{stream::FuturesUnordered, FutureExt, TryStreamExt};
 use serde::Deserialize;
 use time::{format_description::well_known, OffsetDateTime};
 use tokio::pin;
@@ -9,16 +9,17 @@ use tracing::{debug, info, Instrument};
 
 use crate::{
     metrics::types::YoutubeQuotaUsageMetric,
-    model::{Creator, LiveStreamDetails, StreamingService},
+    model::{ChannelStats, Creator, LiveStreamDetails, ScheduledStream, StreamingService},
 };
 
 use self::{
-    api::{get_creator_info, get_video_info, ApiKey, ApiKeyRef, CreatorInfo, YoutubeHandleRef},
-    scraping::{get_channel_id, get_livestream_video_id},
+    api::{get_creator_info, get_videos_info, ApiKey, ApiKeyRef, ChannelIdRef, YoutubeHandleRef},
+    scraping::{get_channel_id, get_livestream_video_id, get_recent_video_ids},
 };
 
 pub mod api;
 mod scraping;
+pub mod websub;
 
 #[derive(Deserialize, Debug)]
 pub struct YoutubeEnvironment {
@@ -39,18 +40,24 @@ pub async fn get_creators(
             let span = tracing::trace_span!("creator_update", ?creator_name);
 
             async move {
+                // Cache this
+                let channel_id = get_channel_id(http_client, creator_name)
+                    .await
+                    .wrap_err("failed to get channel id")?;
+
                 tokio::try_join!(
-                    // Cache this and or the user ID
-                    get_creator_info_from_handle(
+                    get_creator_info(
                         http_client,
                         &environment.api_key,
-                        creator_name,
+                        channel_id.clone(),
                         youtube_quota_usage
-                    ),
+                    )
+                    .map(|creator_info| creator_info.wrap_err("failed to get creator info")),
                     get_livestream_details(
                         http_client,
                         &environment.api_key,
                         creator_name,
+                        &channel_id,
                         youtube_quota_usage
                     )
                 )
@@ -63,7 +70,7 @@ pub async fn get_creators(
 
     // Drive all futures to completion, collecting their results
     futures
-        .map_ok(|(creator_info, livestream_details)| {
+        .map_ok(|(creator_info, broadcasts)| {
             let display_name = creator_info
                 .snippet
                 .title
@@ -81,6 +88,22 @@ pub async fn get_creators(
                 .custom_url
                 .expect("custom_url field should be present in snippet");
 
+            let statistics = creator_info.statistics;
+            let subscribers = if statistics.hidden_subscriber_count == Some(true) {
+                None
+            } else {
+                statistics.subscriber_count.map(|subscribers| {
+                    subscribers
+                        .parse()
+                        .expect("subscriber_count should be a valid integer")
+                })
+            };
+            let views = statistics.view_count.map(|views| {
+                views
+                    .parse()
+                    .expect("view_count should be a valid integer")
+            });
+
             Creator {
                 service: StreamingService::Youtube,
                 id: creator_info.id.take(),
@@ -88,27 +111,43 @@ pub async fn get_creators(
                 href: format!("https://youtube.com/{custom_url}"),
                 handle: custom_url,
                 icon_url,
-                stream: livestream_details,
+                streams: broadcasts.live,
+                upcoming: broadcasts.upcoming,
+                charity: None,
+                stats: Some(ChannelStats {
+                    followers: None,
+                    subscribers,
+                    views,
+                }),
             }
         })
         .try_collect()
         .await
 }
 
-#[tracing::instrument(skip(http_client, api_key, youtube_quota_usage))]
-async fn get_creator_info_from_handle(
+/// The live and scheduled broadcasts of a channel
+#[derive(Debug, Default)]
+pub struct ChannelBroadcasts {
+    pub live: Vec<LiveStreamDetails>,
+    pub upcoming: Vec<ScheduledStream>,
+}
+
+/// Get the current live streams of a single creator
+pub async fn get_creator_stream(
     http_client: &reqwest::Client,
-    api_key: &ApiKeyRef,
-    handle: &YoutubeHandleRef,
+    creator_name: &YoutubeHandleRef,
+    channel_id: &ChannelIdRef,
+    environment: &YoutubeEnvironment,
     youtube_quota_usage: &YoutubeQuotaUsageMetric,
-) -> color_eyre::Result<CreatorInfo> {
-    let channel_id = get_channel_id(http_client, handle)
-        .await
-        .wrap_err("failed to get channel id")?;
-
-    get_creator_info(http_client, api_key, channel_id, youtube_quota_usage)
-        .await
-        .wrap_err("failed to get creator info")
+) -> color_eyre::Result<ChannelBroadcasts> {
+    get_livestream_details(
+        http_client,
+        &environment.api_key,
+        creator_name,
+        channel_id,
+        youtube_quota_usage,
+    )
+    .await
 }
 
 #[tracing::instrument(skip(http_client, api_key, youtube_quota_usage))]
@@ -116,57 +155,150 @@ async fn get_livestream_details(
     http_client: &reqwest::Client,
     api_key: &ApiKeyRef,
     creator_name: &YoutubeHandleRef,
+    channel_id: &ChannelIdRef,
     youtube_quota_usage: &YoutubeQuotaUsageMetric,
-) -> color_eyre::Result<Option<LiveStreamDetails>> {
-    let video_id = get_livestream_video_id(http_client, creator_name)
+) -> color_eyre::Result<ChannelBroadcasts> {
+    // The live page only ever links to a single broadcast, so any concurrent broadcasts
+    // are found through the channel's recent videos. Those are limited to the latest uploads,
+    // see `get_recent_video_ids`
+    let (live_video_id, recent_video_ids) = tokio::try_join!(
+        get_livestream_video_id(http_client, creator_name)
+            .map(|video_id| video_id.wrap_err("failed to get video id")),
+        get_recent_video_ids(http_client, channel_id)
+            .map(|video_ids| video_ids.wrap_err("failed to get recent videos")),
+    )?;
+
+    let mut video_ids = Vec::with_capacity(recent_video_ids.len() + 1);
+    for video_id in live_video_id.into_iter().chain(recent_video_ids) {
+        if !video_ids.contains(&video_id) {
+            video_ids.push(video_id);
+        }
+    }
+
+    let videos = get_videos_info(http_client, api_key, &video_ids, youtube_quota_usage)
         .await
-        .wrap_err("failed to get video id")?;
+        .wrap_err("failed to get video info")?;
+
+    let mut broadcasts = ChannelBroadcasts::default();
+
+    for video_info in videos {
+        let video_id = video_info.id;
+        let href = format!("https://youtube.com/watch?v={video_id}");
 
-    if let Some(video_id) = video_id {
-        debug!(%video_id, "creator has live stream");
+        match video_info.snippet.live_broadcast_content.as_deref() {
+            Some("live") => {}
+            Some("upcoming") => {
+                let live_streaming_details = video_info
+                    .live_streaming_details
+                    .expect("liveStreamingDetails part should exist for upcoming broadcasts");
+                let start_time = live_streaming_details
+                    .scheduled_start_time
+                    .expect("scheduled_start_time field should be present in liveStreamingDetails");
+                let end_time = live_streaming_details.scheduled_end_time;
 
-        let video_info = get_video_info(http_client, api_key, &video_id, youtube_quota_usage)
-            .await
-            .wrap_err("failed to get video info")?;
+                let scheduled_stream = ScheduledStream {
+                    id: video_id,
+                    href,
+                    title: video_info
+                        .snippet
+                        .title
+                        .expect("title should be present in snippet"),
+                    start_time: OffsetDateTime::parse(&start_time, &well_known::Rfc3339)
+                        .expect("scheduled_start_time should be a valid RFC3339 date-time"),
+                    end_time: end_time.map(|end_time| {
+                        OffsetDateTime::parse(&end_time, &well_known::Rfc3339)
+                            .expect("scheduled_end_time should be a valid RFC3339 date-time")
+                    }),
+                };
 
-        // Return early if the channel is not live
-        if !matches!(
-            video_info.snippet.live_broadcast_content.as_deref(),
-            Some("live")
-        ) {
-            return Ok(None);
+                debug!(?scheduled_stream, "creator has a scheduled stream");
+
+                broadcasts.upcoming.push(scheduled_stream);
+
+                continue;
+            }
+            _ => continue,
         }
 
-        let start_time = video_info
+        let live_streaming_details = video_info
             .live_streaming_details
+            .expect("liveStreamingDetails part should exist for live broadcasts");
+        let start_time = live_streaming_details
             .actual_start_time
             .expect("actual_start_time field should be present in liveStreamingDetails");
-        let concurrent_viewers =
-            video_info
-                .live_streaming_details
-                .concurrent_viewers
-                .map(|viewers| {
-                    viewers
-                        .parse()
-                        .expect("concurrent_viewers should be a valid integer")
-                });
-        let title = video_info
+        let concurrent_viewers = live_streaming_details.concurrent_viewers.map(|viewers| {
+            viewers
+                .parse()
+                .expect("concurrent_viewers should be a valid integer")
+        });
+        let thumbnail_url = video_info
             .snippet
-            .title
-            .expect("title should be present in snippet");
+            .thumbnails
+            .and_then(|thumbnails| thumbnails.medium)
+            .and_then(|thumbnail| thumbnail.url);
+        let mature = video_info
+            .content_details
+            .content_rating
+            .and_then(|content_rating| content_rating.yt_rating)
+            .map_or(false, |yt_rating| yt_rating == "ytAgeRestricted");
 
         let livestream_details = LiveStreamDetails {
-            href: format!("https://youtube.com/watch?v={video_id}"),
-            title,
+            href,
+            title: video_info
+                .snippet
+                .title
+                .expect("title should be present in snippet"),
             start_time: OffsetDateTime::parse(&start_time, &well_known::Rfc3339)
                 .expect("start_time should be a valid RFC3339 date-time"),
             viewers: concurrent_viewers,
+            category: video_info
+                .snippet
+                .category_id
+                .as_deref()
+                .and_then(category_name)
+                .map(str::to_owned),
+            tags: video_info.snippet.tags.unwrap_or_default(),
+            language: video_info
+                .snippet
+                .default_audio_language
+                .or(video_info.snippet.default_language),
+            mature,
+            thumbnail_url,
+            campaign: false,
         };
 
         info!(?livestream_details, "creator is live");
 
-        Ok(Some(livestream_details))
-    } else {
-        Ok(None)
+        broadcasts.live.push(livestream_details);
     }
+
+    broadcasts
+        .upcoming
+        .sort_by_key(|scheduled| scheduled.start_time);
+
+    Ok(broadcasts)
+}
+
+/// Name of a video category, these are fixed by youtube so are not worth the quota to look up
+///
+/// <https://developers.google.com/youtube/v3/docs/videoCategories/list>
+fn category_name(category_id: &str) -> Option<&'static str> {
+    Some(match category_id {
+        "1" => "Film & Animation",
+        "2" => "Autos & Vehicles",
+        "10" => "Music",
+        "15" => "Pets & Animals",
+        "17" => "Sports",
+        "19" => "Travel & Events",
+        "20" => "Gaming",
+        "22" => "People & Blogs",
+        "23" => "Comedy",
+        "24" => "Entertainment",
+        "25" => "News & Politics",
+        "26" => "Howto & Style",
+        "27" => "Education",
+        "28" => "Science & Technology",
+        "29" => "Nonprofits & Activism",
+        _ => return None,
+    })
 }
@@ -1,7 +1,8 @@
 use std::fmt::Debug;
 
 use google_youtube3::api::{
-    ChannelListResponse, ChannelSnippet, VideoListResponse, VideoLiveStreamingDetails, VideoSnippet,
+    ChannelListResponse, ChannelSnippet, ChannelStatistics, VideoContentDetails, VideoListResponse,
+    VideoLiveStreamingDetails, VideoSnippet,
 };
 use hyper::StatusCode;
 use once_cell::sync::Lazy;
@@ -41,6 +42,7 @@ pub enum WebError {
 
 pub struct CreatorInfo {
     pub snippet: ChannelSnippet,
+    pub statistics: ChannelStatistics,
     pub id: ChannelId,
 }
 
@@ -61,7 +63,8 @@ pub async fn get_creator_info(
         let mut url = CHANNEL_API_URL.clone();
 
         url.query_pairs_mut()
-            .append_pair("part", "snippet")
+            // Parts do not cost extra quota
+            .append_pair("part", "snippet,statistics")
             .append_pair("id", channel_id.as_str())
             .append_pair("key", api_key.as_str());
 
@@ -112,48 +115,66 @@ pub async fn get_creator_info(
         snippet: channel
             .snippet
             .expect("snippet part should exist in response"),
+        statistics: channel
+            .statistics
+            .expect("statistics part should exist in response"),
     })
 }
 
 #[derive(Debug)]
 pub struct VideoInfo {
-    pub live_streaming_details: VideoLiveStreamingDetails,
+    pub id: VideoId,
+    /// Only present for live, upcoming and past broadcasts
+    pub live_streaming_details: Option<VideoLiveStreamingDetails>,
     pub snippet: VideoSnippet,
+    pub content_details: VideoContentDetails,
 }
 
+/// Get information about multiple videos at once, videos that do not exist are skipped
 // TODO: API CLIENT STRUCT TO HOLD ONTO METRIC AND API KEY
 #[tracing::instrument(skip(http_client, api_key, youtube_quota_usage))]
-pub async fn get_video_info(
+pub async fn get_videos_info(
     http_client: &reqwest::Client,
     api_key: &ApiKeyRef,
-    video_id: &VideoIdRef,
+    video_ids: &[VideoId],
     youtube_quota_usage: &YoutubeQuotaUsageMetric,
-) -> Result<VideoInfo, WebError> {
+) -> Result<Vec<VideoInfo>, WebError> {
     // Video API endpoint
     static VIDEO_API_URL: Lazy<Url> = Lazy::new(|| {
         Url::parse("https://www.googleapis.com/youtube/v3/videos").expect("url should be valid")
     });
 
-    // Create the video api url for the specific video id
+    if video_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Create the video api url for the specific video ids
     let video_api_url = {
         let mut url = VIDEO_API_URL.clone();
 
+        let video_ids = video_ids
+            .iter()
+            .map(VideoId::as_str)
+            .collect::<Vec<_>>()
+            .join(",");
+
         url.query_pairs_mut()
-            .append_pair("part", "snippet,liveStreamingDetails")
-            .append_pair("id", video_id.as_str())
+            .append_pair("part", "snippet,liveStreamingDetails,contentDetails")
+            .append_pair("id", &video_ids)
+            .append_pair("maxResults", "50")
             .append_pair("key", api_key.as_str());
 
         url
     };
 
-    // Get more information about the given video
+    // Get more information about the given videos
     let request = http_client
         .get(video_api_url)
         .header("accept", "application/json")
         .build()
         .expect("youtube api request should be a valid request");
 
-    // 1 quota unit
+    // 1 quota unit, no matter the number of videos
     // https://developers.google.com/youtube/v3/getting-started#calculating-quota-usage
     youtube_quota_usage.inc_by(1);
 
@@ -171,26 +192,20 @@ pub async fn get_video_info(
         .await
         .map_err(|err| WebError::Body(err.without_url()))?;
 
-    // Extract the items
-    let mut items = response.items.expect("items part should exist in response");
-
-    // Get the first video response (the only one)
-    let video = items
-        .pop()
-        .expect("video provided by canonical link must exist");
-
-    // Warn if there were more than 1 video returned from the API
-    if !items.is_empty() {
-        warn!("multiple videos were provided from the API response");
-    }
-
     // Extract important information from the response
-    Ok(VideoInfo {
-        live_streaming_details: video
-            .live_streaming_details
-            .expect("liveStreamingDetails part should exist in response"),
-        snippet: video
-            .snippet
-            .expect("snippet part should exist in response"),
-    })
+    Ok(response
+        .items
+        .expect("items part should exist in response")
+        .into_iter()
+        .map(|video| VideoInfo {
+            id: video.id.expect("id should exist in response").into(),
+            live_streaming_details: video.live_streaming_details,
+            snippet: video
+                .snippet
+                .expect("snippet part should exist in response"),
+            content_details: video
+                .content_details
+                .expect("contentDetails part should exist in response"),
+        })
+        .collect())
 }
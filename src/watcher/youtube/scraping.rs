@@ -3,7 +3,10 @@ use once_cell::sync::Lazy;
 use reqwest::Url;
 use scraper::{Html, Selector};
 
-use super::api::{ChannelId, VideoId, WebError, YoutubeHandleRef};
+use super::{
+    api::{ChannelId, ChannelIdRef, VideoId, WebError, YoutubeHandleRef},
+    websub::Feed,
+};
 
 #[tracing::instrument(skip(http_client))]
 pub async fn get_livestream_video_id(
@@ -37,6 +40,39 @@ pub async fn get_livestream_video_id(
     Ok(Some(video_id.into_owned().into()))
 }
 
+/// Get the most recent videos of a channel from its RSS feed, including live and upcoming
+/// broadcasts, without spending any quota. The feed only lists the 15 latest uploads, so a
+/// broadcast scheduled long in advance can drop off it. Finding those would take the search
+/// API, at 100 quota units a channel
+#[tracing::instrument(skip(http_client))]
+pub async fn get_recent_video_ids(
+    http_client: &reqwest::Client,
+    channel_id: &ChannelIdRef,
+) -> color_eyre::Result<Vec<VideoId>> {
+    static FEED_URL: Lazy<Url> = Lazy::new(|| {
+        Url::parse("https://www.youtube.com/feeds/videos.xml").expect("url should be valid")
+    });
+
+    let mut url = FEED_URL.clone();
+    url.query_pairs_mut()
+        .append_pair("channel_id", channel_id.as_str());
+
+    let response = http_client
+        .get(url)
+        .send()
+        .await
+        .map_err(WebError::Request)?
+        .error_for_status()
+        .map_err(|err| WebError::Status(err.status().expect("status should exist on error")))?
+        .text()
+        .await
+        .map_err(WebError::Body)?;
+
+    let feed: Feed = quick_xml::de::from_str(&response).wrap_err("incompatible channel feed")?;
+
+    Ok(feed.entry.into_iter().map(|entry| entry.video_id).collect())
+}
+
 #[tracing::instrument(skip(http_client))]
 pub async fn get_channel_id(
     http_client: &reqwest::Client,
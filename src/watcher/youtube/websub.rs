@@ -0,0 +1,278 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    time::Duration,
+};
+
+use color_eyre::eyre::Context;
+use futures::{stream::FuturesUnordered, StreamExt};
+use once_cell::sync::Lazy;
+use reqwest::Url;
+use serde::Deserialize;
+use tokio::{
+    pin,
+    sync::{mpsc, watch},
+    time::Instant,
+};
+use tracing::{debug, error, trace};
+
+use crate::{model::StreamingService, watcher::WatcherDataReceive};
+
+use super::api::{ChannelId, ChannelIdRef, VideoId};
+
+/// Lease requested from the hub, the hub may grant a shorter one
+const LEASE: Duration = Duration::from_secs(5 * 24 * 60 * 60); // 5 days
+/// Time between checking for leases that need to be renewed
+const RENEWAL_CHECK_PERIOD: Duration = Duration::from_secs(60 * 60); // 1 hour
+
+static HUB_URL: Lazy<Url> = Lazy::new(|| {
+    Url::parse("https://pubsubhubbub.appspot.com/subscribe").expect("url should be valid")
+});
+
+#[aliri_braid::braid(serde, display = "omit", debug = "omit")]
+pub struct WebSubSecret;
+
+impl Debug for WebSubSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "*****")
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebSubEnvironment {
+    /// Publicly reachable url of the websub callback on the web server
+    #[serde(rename = "youtube_websub_callback_url")]
+    pub callback_url: Url,
+    /// Secret the hub uses to sign notifications
+    #[serde(rename = "youtube_websub_secret")]
+    pub secret: WebSubSecret,
+}
+
+/// The feed topic the hub publishes a channel's uploads and live broadcasts to
+pub fn topic_url(channel_id: &ChannelIdRef) -> Url {
+    let mut url =
+        Url::parse("https://www.youtube.com/xml/feeds/videos.xml").expect("url should be valid");
+
+    url.query_pairs_mut()
+        .append_pair("channel_id", channel_id.as_str());
+
+    url
+}
+
+/// The lease the hub granted when verifying a subscription to a channel's feed
+#[derive(Debug)]
+pub struct GrantedLease {
+    pub channel_id: ChannelId,
+    pub lease: Duration,
+}
+
+/// Extract the channel id from a feed topic url
+pub fn topic_channel_id(topic: &str) -> Option<ChannelId> {
+    let topic = Url::parse(topic).ok()?;
+
+    if topic.host_str() != Some("www.youtube.com") || topic.path() != "/xml/feeds/videos.xml" {
+        return None;
+    }
+
+    topic
+        .query_pairs()
+        .find(|(key, _)| key == "channel_id")
+        .map(|(_, value)| value.into_owned().into())
+}
+
+/// An Atom feed of a channel's videos, as sent by the hub or served as the channel's RSS feed
+#[derive(Debug, Deserialize)]
+pub(super) struct Feed {
+    #[serde(default)]
+    pub entry: Vec<Entry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct Entry {
+    #[serde(rename = "videoId")]
+    pub video_id: VideoId,
+    #[serde(rename = "channelId")]
+    pub channel_id: ChannelId,
+}
+
+/// Get the channels that had a video published or updated from an Atom notification
+pub fn parse_notification(body: &str) -> color_eyre::Result<HashSet<ChannelId>> {
+    let feed: Feed = quick_xml::de::from_str(body).wrap_err("incompatible websub notification")?;
+
+    Ok(feed
+        .entry
+        .into_iter()
+        .map(|entry| {
+            trace!(video_id = %entry.video_id, %entry.channel_id, "websub notification entry");
+
+            entry.channel_id
+        })
+        .collect())
+}
+
+/// Subscribe to, and keep renewing, the feeds of every youtube creator
+#[tracing::instrument(skip_all)]
+pub async fn websub_subscriber(
+    http_client: reqwest::Client,
+    environment: WebSubEnvironment,
+    mut watcher_data: watch::Receiver<WatcherDataReceive>,
+    mut leases: mpsc::Receiver<GrantedLease>,
+) {
+    let mut renew_at = HashMap::<ChannelId, Instant>::new();
+
+    let mut interval = tokio::time::interval(RENEWAL_CHECK_PERIOD);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            changed = watcher_data.changed() => {
+                if changed.is_err() {
+                    // Channel closed
+                    break;
+                }
+            }
+            Some(granted) = leases.recv() => {
+                debug!(%granted.channel_id, lease = ?granted.lease, "websub lease granted");
+
+                // Renew at half of the lease to leave room for failures
+                renew_at.insert(granted.channel_id, Instant::now() + granted.lease / 2);
+
+                continue;
+            }
+        }
+
+        let channels: Vec<ChannelId> = watcher_data
+            .borrow()
+            .iter()
+            .flat_map(|data| data.creators.iter())
+            .filter(|creator| creator.service == StreamingService::Youtube)
+            .map(|creator| ChannelId::from(creator.id.clone()))
+            .collect();
+
+        let now = Instant::now();
+
+        let futures: FuturesUnordered<_> = channels
+            .into_iter()
+            .filter(|channel_id| {
+                renew_at
+                    .get(channel_id)
+                    .map_or(true, |renew_at| *renew_at <= now)
+            })
+            .map(|channel_id| {
+                let http_client = &http_client;
+                let environment = &environment;
+
+                async move {
+                    let result = subscribe(http_client, environment, &channel_id).await;
+
+                    (channel_id, result)
+                }
+            })
+            .collect();
+
+        pin!(futures);
+
+        while let Some((channel_id, result)) = futures.next().await {
+            match result {
+                Ok(()) => {
+                    // Until the hub verifies the subscription with the lease it granted
+                    renew_at.insert(channel_id, now + LEASE / 2);
+                }
+                Err(error) => {
+                    error!(?error, %channel_id, "failed to subscribe to channel feed");
+                }
+            }
+        }
+    }
+}
+
+#[tracing::instrument(skip(http_client, environment))]
+async fn subscribe(
+    http_client: &reqwest::Client,
+    environment: &WebSubEnvironment,
+    channel_id: &ChannelIdRef,
+) -> color_eyre::Result<()> {
+    let topic = topic_url(channel_id);
+
+    http_client
+        .post(HUB_URL.clone())
+        .form(&[
+            ("hub.callback", environment.callback_url.as_str()),
+            ("hub.topic", topic.as_str()),
+            ("hub.mode", "subscribe"),
+            ("hub.verify", "async"),
+            ("hub.lease_seconds", LEASE.as_secs().to_string().as_str()),
+            ("hub.secret", environment.secret.as_str()),
+        ])
+        .send()
+        .await
+        .wrap_err("websub hub request failed")?
+        .error_for_status()
+        .wrap_err("websub hub returned non success status code")?;
+
+    debug!(%topic, "requested websub subscription");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A notification as the hub sends it for a new or updated video
+    const NOTIFICATION: &str = r#"<?xml version='1.0' encoding='UTF-8'?>
+<feed xmlns:yt="http://www.youtube.com/xml/schemas/2015" xmlns="http://www.w3.org/2005/Atom">
+  <link rel="hub" href="https://pubsubhubbub.appspot.com"/>
+  <link rel="self" href="https://www.youtube.com/xml/feeds/videos.xml?channel_id=UCuAXFkgsw1L7xaCfnd5JJOw"/>
+  <title>YouTube video feed</title>
+  <updated>2023-02-18T18:00:01.000000000+00:00</updated>
+  <entry>
+    <id>yt:video:dQw4w9WgXcQ</id>
+    <yt:videoId>dQw4w9WgXcQ</yt:videoId>
+    <yt:channelId>UCuAXFkgsw1L7xaCfnd5JJOw</yt:channelId>
+    <title>Creators for a Cause</title>
+    <link rel="alternate" href="https://www.youtube.com/watch?v=dQw4w9WgXcQ"/>
+    <author>
+      <name>Creator</name>
+      <uri>https://www.youtube.com/channel/UCuAXFkgsw1L7xaCfnd5JJOw</uri>
+    </author>
+    <published>2023-02-18T18:00:00+00:00</published>
+    <updated>2023-02-18T18:00:01.000000000+00:00</updated>
+  </entry>
+</feed>"#;
+
+    /// A notification for a deleted video, which has no entries
+    const DELETION: &str = r#"<feed xmlns:at="http://purl.org/atompub/tombstones/1.0" xmlns="http://www.w3.org/2005/Atom">
+  <at:deleted-entry ref="yt:video:dQw4w9WgXcQ" when="2023-02-18T18:00:00+00:00">
+    <link href="https://www.youtube.com/watch?v=dQw4w9WgXcQ"/>
+  </at:deleted-entry>
+</feed>"#;
+
+    #[test]
+    fn parses_notifications() {
+        let channel_ids = parse_notification(NOTIFICATION).expect("notification should parse");
+
+        assert_eq!(
+            channel_ids,
+            HashSet::from([ChannelId::from_static("UCuAXFkgsw1L7xaCfnd5JJOw")])
+        );
+        assert!(parse_notification(DELETION)
+            .expect("deletion should parse")
+            .is_empty());
+    }
+
+    #[test]
+    fn reads_channel_ids_from_topics() {
+        let topic = topic_url(ChannelIdRef::from_str("UCuAXFkgsw1L7xaCfnd5JJOw"));
+
+        assert_eq!(
+            topic_channel_id(topic.as_str()),
+            Some(ChannelId::from_static("UCuAXFkgsw1L7xaCfnd5JJOw"))
+        );
+        assert_eq!(
+            topic_channel_id("https://example.com/xml/feeds/videos.xml?channel_id=UC"),
+            None
+        );
+    }
+}
@@ -2,26 +2,114 @@ use std::{net::SocketAddr, time::Duration};
 
 use axum::{extract::State, routing::get, Json, Router, Server};
 use sentry_tower::{SentryHttpLayer, SentryLayer};
-use tokio::sync::watch;
+use serde::Deserialize;
+use tokio::sync::{mpsc, watch};
 use tower_http::{
     catch_panic::CatchPanicLayer, cors::CorsLayer, timeout::TimeoutLayer, trace::TraceLayer,
 };
 use tracing::info;
 
-use crate::{watcher::WatcherDataReceive, web::layers::helmet};
+use crate::{
+    store::{HistorySummaryReceive, Store},
+    watcher::{
+        tiltify::{WebhookEvent, WebhookSecret},
+        youtube::api::ChannelId,
+        WatcherDataReceive,
+    },
+    web::layers::helmet,
+};
 
+mod api_v1;
+mod calendar;
+mod campaign_history;
+mod history;
 mod layers;
 mod live_view;
 mod markup;
+mod push;
 mod static_assets;
+mod stream;
+mod tiltify_webhook;
+mod youtube_websub;
+
+pub use youtube_websub::WebSubCallback;
+
+#[derive(Debug, Deserialize)]
+pub struct WebEnvironment {
+    /// Secret used to verify tiltify webhook signatures, webhooks are disabled if not set
+    tiltify_webhook_secret: Option<WebhookSecret>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn web_server(
+    listen: SocketAddr,
+    environment: WebEnvironment,
+    watcher_data: watch::Receiver<WatcherDataReceive>,
+    tiltify_events: mpsc::Sender<WebhookEvent>,
+    websub_callback: Option<WebSubCallback>,
+    youtube_notifications: mpsc::Sender<ChannelId>,
+    store: Store,
+    history_summary: watch::Receiver<HistorySummaryReceive>,
+    push_public_key: Option<String>,
+) {
+    let mut app = Router::new();
+
+    if let Some(secret) = environment.tiltify_webhook_secret {
+        app = app.nest(
+            "/webhooks/tiltify",
+            tiltify_webhook::router(secret, tiltify_events),
+        );
+    } else {
+        info!("no tiltify webhook secret provided, relying on polling alone");
+    }
+
+    if let Some(callback) = websub_callback {
+        app = app.nest(
+            "/webhooks/youtube",
+            youtube_websub::router(callback, watcher_data.clone(), youtube_notifications),
+        );
+    }
+
+    if let Some(public_key) = push_public_key {
+        app = app.nest(
+            "/api/push",
+            push::router(store.clone(), public_key, watcher_data.clone()),
+        );
+    } else {
+        info!("no vapid key provided, web push is disabled");
+    }
 
-pub async fn web_server(listen: SocketAddr, watcher_data: watch::Receiver<WatcherDataReceive>) {
-    let app = Router::new()
+    let app = app
+        .nest("/api/v1", api_v1::router(watcher_data.clone()))
+        .route("/api/openapi.json", get(api_v1::openapi))
         .nest(
             "/live-view",
-            live_view::router(listen, watcher_data.clone()),
+            live_view::router(listen, watcher_data.clone(), history_summary.clone()),
         )
         .route("/health", get(|| async { "OK" }))
+        .route_service(
+            "/schedule.ics",
+            get(calendar::handler).with_state(watcher_data.clone()),
+        )
+        .route_service(
+            "/api/history",
+            get(history::handler).with_state(history::HistoryState {
+                store: store.clone(),
+                watcher_data: watcher_data.clone(),
+            }),
+        )
+        .route_service(
+            "/api/campaign/history",
+            get(campaign_history::handler).with_state(store),
+        )
+        .route_service(
+            "/api/campaign/pace",
+            get(campaign_history::pace).with_state(history_summary),
+        )
+        .route_service(
+            "/api/stream",
+            get(stream::handler).with_state(watcher_data.clone()),
+        )
         .route_service("/json", get(json).with_state(watcher_data))
         .fallback_service(get(static_assets::handler))
         .layer(
@@ -0,0 +1,448 @@
+//! Version 1 of the public API, for overlays and bots.
+//!
+//! Responses are built from their own types rather than the internal model, so the model can
+//! change without breaking consumers. Fields may be added to a version, but never renamed,
+//! removed or have their meaning changed
+use std::{cmp::Reverse, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderValue,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use hyper::{header, StatusCode};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::sync::watch;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::{
+    model,
+    watcher::{WatcherData, WatcherDataReceive},
+};
+
+pub fn router(watcher_data: watch::Receiver<WatcherDataReceive>) -> Router {
+    Router::new()
+        .route("/creators", get(creators))
+        .route("/creators/:service/:id", get(creator))
+        .route("/campaign", get(campaign))
+        .route("/status", get(status))
+        .with_state(watcher_data)
+}
+
+/// The OpenAPI document describing this version
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Creators for a Cause",
+        description = "Live status of the campaign's creators, and how much the campaign has raised"
+    ),
+    paths(creators, creator, campaign, status),
+    components(schemas(
+        Campaign,
+        Creator,
+        CreatorSort,
+        LiveStreamDetails,
+        Money,
+        ScheduledStream,
+        Status,
+        StreamingService
+    ))
+)]
+pub struct ApiDoc;
+
+/// The document is the same for the life of the server
+static DOCUMENT: Lazy<String> = Lazy::new(|| {
+    ApiDoc::openapi()
+        .to_pretty_json()
+        .expect("openapi document should serialize")
+});
+
+#[axum::debug_handler]
+pub async fn openapi() -> impl IntoResponse {
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        )],
+        DOCUMENT.as_str(),
+    )
+}
+
+/// The data, or 503 if the watcher has not populated it yet
+fn current(
+    watcher_data: &watch::Receiver<WatcherDataReceive>,
+) -> Result<Arc<WatcherData>, StatusCode> {
+    watcher_data
+        .borrow()
+        .clone()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CreatorsQuery {
+    /// Only include creators on this service
+    service: Option<StreamingService>,
+    /// Only include creators that are live
+    #[serde(default)]
+    live: bool,
+    #[serde(default)]
+    sort: CreatorSort,
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CreatorSort {
+    /// Alphabetically by display name
+    #[default]
+    Name,
+    /// Most viewers across all streams first
+    Viewers,
+}
+
+/// The creators of the campaign
+#[utoipa::path(
+    get,
+    path = "/api/v1/creators",
+    tag = "v1",
+    params(CreatorsQuery),
+    responses(
+        (status = 200, description = "The matching creators", body = [Creator]),
+        (status = 503, description = "The server has no data yet"),
+    )
+)]
+#[tracing::instrument(skip(watcher_data))]
+#[axum::debug_handler]
+async fn creators(
+    State(watcher_data): State<watch::Receiver<WatcherDataReceive>>,
+    Query(query): Query<CreatorsQuery>,
+) -> Result<Json<Vec<Creator>>, StatusCode> {
+    let data = current(&watcher_data)?;
+
+    let mut creators = data
+        .creators
+        .iter()
+        .filter(|creator| {
+            query
+                .service
+                .map_or(true, |service| {
+                    creator.service == model::StreamingService::from(service)
+                })
+        })
+        .filter(|creator| !query.live || creator.is_live())
+        .map(Creator::from)
+        .collect::<Vec<_>>();
+
+    let by_name = |creator: &Creator| creator.display_name.to_lowercase();
+
+    match query.sort {
+        CreatorSort::Name => creators.sort_by_cached_key(by_name),
+        CreatorSort::Viewers => {
+            creators.sort_by_cached_key(|creator| (Reverse(creator.viewers), by_name(creator)))
+        }
+    }
+
+    Ok(Json(creators))
+}
+
+/// A single creator
+#[utoipa::path(
+    get,
+    path = "/api/v1/creators/{service}/{id}",
+    tag = "v1",
+    params(
+        ("service" = StreamingService, Path, description = "The service the creator streams on"),
+        ("id" = String, Path, description = "The creator's ID on the service"),
+    ),
+    responses(
+        (status = 200, description = "The creator", body = Creator),
+        (status = 404, description = "No such creator in the campaign"),
+        (status = 503, description = "The server has no data yet"),
+    )
+)]
+#[tracing::instrument(skip(watcher_data))]
+#[axum::debug_handler]
+async fn creator(
+    State(watcher_data): State<watch::Receiver<WatcherDataReceive>>,
+    Path((service, id)): Path<(StreamingService, String)>,
+) -> Result<Json<Creator>, StatusCode> {
+    let data = current(&watcher_data)?;
+
+    data.creators
+        .iter()
+        .find(|creator| creator.is(service.into(), &id))
+        .map(|creator| Json(Creator::from(creator)))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// The campaign, and how much it has raised
+#[utoipa::path(
+    get,
+    path = "/api/v1/campaign",
+    tag = "v1",
+    responses(
+        (status = 200, description = "The campaign", body = Campaign),
+        (status = 503, description = "The server has no data yet"),
+    )
+)]
+#[tracing::instrument(skip(watcher_data))]
+#[axum::debug_handler]
+async fn campaign(
+    State(watcher_data): State<watch::Receiver<WatcherDataReceive>>,
+) -> Result<Json<Campaign>, StatusCode> {
+    let data = current(&watcher_data)?;
+
+    Ok(Json(Campaign::from(&*data)))
+}
+
+/// Health of the server and freshness of its data
+#[utoipa::path(
+    get,
+    path = "/api/v1/status",
+    tag = "v1",
+    responses((status = 200, description = "The status", body = Status))
+)]
+#[tracing::instrument(skip(watcher_data))]
+#[axum::debug_handler]
+async fn status(
+    State(watcher_data): State<watch::Receiver<WatcherDataReceive>>,
+) -> Json<Status> {
+    let data = watcher_data.borrow().clone();
+
+    Json(Status {
+        version: git_version::git_version!(args = ["--always"]),
+        ready: data.is_some(),
+        updated: data.as_ref().map(|data| data.updated),
+        restored: data.as_ref().map_or(false, |data| data.restored),
+        creators: data.as_ref().map_or(0, |data| data.creators.len()),
+        live_creators: data.as_ref().map_or(0, |data| {
+            data.creators.iter().filter(|creator| creator.is_live()).count()
+        }),
+    })
+}
+
+/// A service creators stream on
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamingService {
+    Twitch,
+    Youtube,
+}
+
+impl From<model::StreamingService> for StreamingService {
+    fn from(service: model::StreamingService) -> Self {
+        match service {
+            model::StreamingService::Twitch => StreamingService::Twitch,
+            model::StreamingService::Youtube => StreamingService::Youtube,
+        }
+    }
+}
+
+impl From<StreamingService> for model::StreamingService {
+    fn from(service: StreamingService) -> Self {
+        match service {
+            StreamingService::Twitch => model::StreamingService::Twitch,
+            StreamingService::Youtube => model::StreamingService::Youtube,
+        }
+    }
+}
+
+/// An exact amount of money
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Money {
+    /// Decimal amount as a string, so no precision is lost
+    pub amount: String,
+    /// ISO 4217 currency code, such as `USD`
+    pub currency: String,
+    /// Human readable amount, such as `$1,234.50`
+    pub formatted: String,
+}
+
+impl From<&model::money::Money> for Money {
+    fn from(money: &model::money::Money) -> Self {
+        Self {
+            amount: money.amount.to_string(),
+            currency: money.currency.to_string(),
+            formatted: money.to_string(),
+        }
+    }
+}
+
+/// A channel on one of the streaming services
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Creator {
+    pub service: StreamingService,
+    /// Unchanging ID of the channel, only unique within a service
+    pub id: String,
+    pub display_name: String,
+    pub handle: String,
+    /// The channel's page
+    pub url: String,
+    pub icon_url: String,
+    pub live: bool,
+    /// Viewers across all of the current streams
+    pub viewers: u32,
+    /// Null if the service does not report it
+    pub followers: Option<u64>,
+    /// Current streams, with the campaign's stream first
+    pub streams: Vec<LiveStreamDetails>,
+    /// Scheduled streams that have not started yet
+    pub upcoming: Vec<ScheduledStream>,
+}
+
+impl From<&model::Creator> for Creator {
+    fn from(creator: &model::Creator) -> Self {
+        Self {
+            service: creator.service.into(),
+            id: creator.id.clone(),
+            display_name: creator.display_name.clone(),
+            handle: creator.handle.clone(),
+            url: creator.href.clone(),
+            icon_url: creator.icon_url.clone(),
+            live: creator.is_live(),
+            viewers: creator
+                .streams
+                .iter()
+                .filter_map(|stream| stream.viewers)
+                .sum(),
+            followers: creator.stats.as_ref().and_then(|stats| stats.followers),
+            streams: creator.streams.iter().map(LiveStreamDetails::from).collect(),
+            upcoming: creator.upcoming.iter().map(ScheduledStream::from).collect(),
+        }
+    }
+}
+
+/// A stream that is live right now
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LiveStreamDetails {
+    pub url: String,
+    pub title: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub started_at: OffsetDateTime,
+    /// Null if the service does not report it
+    pub viewers: Option<u32>,
+    /// The game or category being streamed
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    /// ISO 639-1 language code
+    pub language: Option<String>,
+    /// Intended for mature audiences
+    pub mature: bool,
+    pub thumbnail_url: Option<String>,
+    /// Whether this is the stream for the campaign
+    pub campaign: bool,
+}
+
+impl From<&model::LiveStreamDetails> for LiveStreamDetails {
+    fn from(stream: &model::LiveStreamDetails) -> Self {
+        Self {
+            url: stream.href.clone(),
+            title: stream.title.clone(),
+            started_at: stream.start_time,
+            viewers: stream.viewers,
+            category: stream.category.clone(),
+            tags: stream.tags.clone(),
+            language: stream.language.clone(),
+            mature: stream.mature,
+            thumbnail_url: stream.thumbnail_url.clone(),
+            campaign: stream.campaign,
+        }
+    }
+}
+
+/// A stream announced ahead of time
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ScheduledStream {
+    pub url: String,
+    pub title: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub starts_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub ends_at: Option<OffsetDateTime>,
+}
+
+impl From<&model::ScheduledStream> for ScheduledStream {
+    fn from(stream: &model::ScheduledStream) -> Self {
+        Self {
+            url: stream.href.clone(),
+            title: stream.title.clone(),
+            starts_at: stream.start_time,
+            ends_at: stream.end_time,
+        }
+    }
+}
+
+/// The fundraising campaign, and how much it has raised
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Campaign {
+    pub id: u32,
+    pub name: String,
+    pub description: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub starts_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub ends_at: Option<OffsetDateTime>,
+    /// Raised through tiltify
+    pub tiltify: Money,
+    /// Raised through fundraisers run natively on twitch
+    pub twitch_charity: Money,
+    pub total: Money,
+    pub goal: Money,
+}
+
+impl From<&WatcherData> for Campaign {
+    fn from(data: &WatcherData) -> Self {
+        Self {
+            id: data.tiltify.id,
+            name: data.tiltify.name.clone(),
+            description: data.tiltify.description.clone(),
+            starts_at: data.tiltify.starts_at,
+            ends_at: data.tiltify.ends_at,
+            tiltify: (&data.totals.tiltify).into(),
+            twitch_charity: (&data.totals.twitch_charity).into(),
+            total: (&data.totals.total).into(),
+            goal: (&data.totals.goal).into(),
+        }
+    }
+}
+
+/// Health of the server and freshness of its data
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Status {
+    /// Git version of the running build
+    pub version: &'static str,
+    /// Whether there is any data yet, the other endpoints respond with 503 until there is
+    pub ready: bool,
+    /// When the data last changed
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub updated: Option<OffsetDateTime>,
+    /// Whether the data was restored from a snapshot and may be stale
+    pub restored: bool,
+    pub creators: usize,
+    pub live_creators: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The checked-in document is what overlay developers read, regenerate it with
+    /// `curl localhost:8080/api/openapi.json > openapi.json` after changing the API
+    #[test]
+    fn openapi_document_is_up_to_date() {
+        let checked_in: serde_json::Value =
+            serde_json::from_str(include_str!("../../openapi.json"))
+                .expect("checked-in openapi.json should be valid JSON");
+        let generated =
+            serde_json::to_value(ApiDoc::openapi()).expect("openapi document should serialize");
+
+        assert_eq!(
+            generated, checked_in,
+            "the API changed, regenerate openapi.json"
+        );
+    }
+}
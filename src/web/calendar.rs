@@ -0,0 +1,207 @@
+use axum::{extract::State, http::HeaderValue, response::IntoResponse};
+use hyper::{header, StatusCode};
+use time::{Date, Duration, OffsetDateTime, UtcOffset};
+use tokio::sync::watch;
+
+use crate::{
+    model::Creator,
+    watcher::{WatcherData, WatcherDataReceive},
+};
+
+/// Assumed length of a stream that has no scheduled end
+const ESTIMATED_STREAM_DURATION: Duration = Duration::hours(3);
+
+/// Domain used to make event UIDs globally unique
+const UID_DOMAIN: &str = "creatorsforacause.fly.dev";
+
+#[tracing::instrument(skip_all)]
+#[axum::debug_handler]
+pub async fn handler(
+    State(watcher_data): State<watch::Receiver<WatcherDataReceive>>,
+) -> impl IntoResponse {
+    let watcher_data = watcher_data.borrow().as_ref().cloned();
+
+    match watcher_data {
+        Some(watcher_data) => (
+            StatusCode::OK,
+            [(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/calendar; charset=utf-8"),
+            )],
+            render_calendar(&watcher_data),
+        ),
+        None => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; charset=utf-8"),
+            )],
+            "the backend has not populated the scraping data".to_string(),
+        ),
+    }
+}
+
+/// Render an RFC 5545 calendar of the campaign and every live or scheduled stream
+fn render_calendar(data: &WatcherData) -> String {
+    let mut calendar = Calendar::default();
+
+    calendar.line("BEGIN:VCALENDAR");
+    calendar.line("VERSION:2.0");
+    calendar.line(concat!(
+        "PRODID:-//creatorsforacause//",
+        env!("CARGO_PKG_NAME"),
+        "//EN"
+    ));
+    calendar.line("CALSCALE:GREGORIAN");
+    calendar.line("METHOD:PUBLISH");
+    calendar.property("X-WR-CALNAME", &data.tiltify.name);
+
+    let stamp = format_date_time(data.updated);
+
+    // The campaign itself, as an all day event
+    {
+        let campaign = &data.tiltify;
+        let start = campaign.starts_at.to_offset(UtcOffset::UTC).date();
+        // All day end dates are exclusive
+        let end = campaign
+            .ends_at
+            .map_or(start, |ends_at| ends_at.to_offset(UtcOffset::UTC).date())
+            .next_day()
+            .expect("campaign should not end at the end of time");
+
+        calendar.line("BEGIN:VEVENT");
+        calendar.line(&format!("UID:campaign-{}@{UID_DOMAIN}", campaign.id));
+        calendar.line(&format!("DTSTAMP:{stamp}"));
+        calendar.line(&format!("DTSTART;VALUE=DATE:{}", format_date(start)));
+        calendar.line(&format!("DTEND;VALUE=DATE:{}", format_date(end)));
+        calendar.property("SUMMARY", &campaign.name);
+        calendar.property("DESCRIPTION", &campaign.description);
+        calendar.line("END:VEVENT");
+    }
+
+    for creator in data.creators.iter() {
+        for stream in &creator.streams {
+            // Live streams run for at least the estimated duration, or until now
+            let end = (stream.start_time + ESTIMATED_STREAM_DURATION).max(data.updated);
+
+            // Live streams have no id of their own, but do not move once started
+            calendar.stream_event(
+                creator,
+                &stream.start_time.unix_timestamp().to_string(),
+                &stamp,
+                &stream.title,
+                &stream.href,
+                stream.start_time,
+                end,
+            );
+        }
+
+        for scheduled in &creator.upcoming {
+            calendar.stream_event(
+                creator,
+                &scheduled.id,
+                &stamp,
+                &scheduled.title,
+                &scheduled.href,
+                scheduled.start_time,
+                scheduled
+                    .end_time
+                    .unwrap_or(scheduled.start_time + ESTIMATED_STREAM_DURATION),
+            );
+        }
+    }
+
+    calendar.line("END:VCALENDAR");
+
+    calendar.0
+}
+
+#[derive(Default)]
+struct Calendar(String);
+
+impl Calendar {
+    /// Write a content line, folding it to at most 75 octets per line
+    fn line(&mut self, line: &str) {
+        let mut octets = 0;
+
+        for char in line.chars() {
+            if octets + char.len_utf8() > 75 {
+                self.0.push_str("\r\n ");
+                // The leading space counts towards the line length
+                octets = 1;
+            }
+
+            self.0.push(char);
+            octets += char.len_utf8();
+        }
+
+        self.0.push_str("\r\n");
+    }
+
+    /// Write a property with an escaped text value
+    fn property(&mut self, name: &str, text: &str) {
+        let mut line = format!("{name}:");
+
+        for char in text.chars() {
+            match char {
+                '\\' | ';' | ',' => {
+                    line.push('\\');
+                    line.push(char);
+                }
+                '\n' => line.push_str("\\n"),
+                '\r' => {}
+                char => line.push(char),
+            }
+        }
+
+        self.line(&line);
+    }
+
+    fn stream_event(
+        &mut self,
+        creator: &Creator,
+        key: &str,
+        stamp: &str,
+        title: &str,
+        href: &str,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) {
+        self.line("BEGIN:VEVENT");
+        // Keyed on something stable, so refreshes and reschedules update rather than
+        // duplicate the event
+        self.line(&format!(
+            "UID:{}-{}-{key}@{UID_DOMAIN}",
+            creator.service, creator.id
+        ));
+        self.line(&format!("DTSTAMP:{stamp}"));
+        self.line(&format!("DTSTART:{}", format_date_time(start)));
+        self.line(&format!("DTEND:{}", format_date_time(end)));
+        self.property("SUMMARY", &format!("{}: {title}", creator.display_name));
+        self.property("DESCRIPTION", &format!("{title}\n{href}"));
+        self.line(&format!("URL:{href}"));
+        self.property("LOCATION", href);
+        self.line("END:VEVENT");
+    }
+}
+
+fn format_date(date: Date) -> String {
+    format!(
+        "{:04}{:02}{:02}",
+        date.year(),
+        u8::from(date.month()),
+        date.day()
+    )
+}
+
+fn format_date_time(date_time: OffsetDateTime) -> String {
+    let date_time = date_time.to_offset(UtcOffset::UTC);
+
+    format!(
+        "{}T{:02}{:02}{:02}Z",
+        format_date(date_time.date()),
+        date_time.hour(),
+        date_time.minute(),
+        date_time.second()
+    )
+}
@@ -0,0 +1,78 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use time::Duration;
+use tokio::sync::watch;
+use tracing::error;
+
+use crate::{
+    config::CONFIG,
+    store::{
+        campaign::{self, AmountSample},
+        pace::Pace,
+        HistorySummaryReceive, Store,
+    },
+};
+
+const DEFAULT_BUCKET_SECONDS: u32 = 60 * 60; // 1 hour
+const MIN_BUCKET_SECONDS: u32 = 60; // 1 minute
+/// Most buckets returned, the most recent ones are kept
+const MAX_BUCKETS: u32 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct CampaignHistoryQuery {
+    /// Width of the time buckets samples are reduced to, in seconds
+    bucket: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CampaignHistory {
+    bucket_seconds: u32,
+    /// The last sample of every bucket with a change, oldest first
+    samples: Vec<AmountSample>,
+}
+
+#[tracing::instrument(skip(store))]
+#[axum::debug_handler]
+pub async fn handler(
+    State(store): State<Store>,
+    Query(query): Query<CampaignHistoryQuery>,
+) -> Result<Json<CampaignHistory>, StatusCode> {
+    let bucket_seconds = query
+        .bucket
+        .unwrap_or(DEFAULT_BUCKET_SECONDS)
+        .max(MIN_BUCKET_SECONDS);
+
+    let result = store
+        .with_connection(move |connection| {
+            campaign::samples(
+                connection,
+                CONFIG.campaign.id,
+                Duration::seconds(bucket_seconds.into()),
+                MAX_BUCKETS,
+            )
+        })
+        .await;
+
+    match result {
+        Ok(samples) => Ok(Json(CampaignHistory {
+            bucket_seconds,
+            samples,
+        })),
+        Err(error) => {
+            error!(?error, "failed to query campaign history");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+#[axum::debug_handler]
+pub async fn pace(
+    State(history_summary): State<watch::Receiver<HistorySummaryReceive>>,
+) -> Json<Option<Pace>> {
+    Json(history_summary.borrow().pace.clone())
+}
@@ -0,0 +1,98 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::error;
+
+use crate::{
+    model::{CreatorKey, StreamingService},
+    store::{
+        sessions::{self, StreamSession},
+        Store,
+    },
+    watcher::WatcherDataReceive,
+};
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Clone)]
+pub struct HistoryState {
+    pub store: Store,
+    /// For the start of the campaign, which streamed time is counted from
+    pub watcher_data: watch::Receiver<WatcherDataReceive>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// Only include sessions of a single creator, requires `id`
+    service: Option<StreamingService>,
+    id: Option<String>,
+    limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct History {
+    sessions: Vec<StreamSession>,
+    streamed: Vec<StreamedTotal>,
+}
+
+#[derive(Debug, Serialize)]
+struct StreamedTotal {
+    service: StreamingService,
+    id: String,
+    /// Seconds spent streaming for the campaign since it started
+    seconds: i64,
+}
+
+#[tracing::instrument(skip(state))]
+#[axum::debug_handler]
+pub async fn handler(
+    State(state): State<HistoryState>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<History>, StatusCode> {
+    let creator = match (query.service, query.id) {
+        (Some(service), Some(id)) => Some(CreatorKey { service, id }),
+        (None, None) => None,
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+
+    let campaign_start = state
+        .watcher_data
+        .borrow()
+        .as_ref()
+        .map(|data| data.tiltify.starts_at)
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let result = state
+        .store
+        .with_connection(move |connection| {
+            Ok((
+                sessions::sessions(connection, creator.as_ref(), limit)?,
+                sessions::streamed_time(connection, campaign_start)?,
+            ))
+        })
+        .await;
+
+    match result {
+        Ok((sessions, streamed)) => Ok(Json(History {
+            sessions,
+            streamed: streamed
+                .into_iter()
+                .map(|(creator, streamed)| StreamedTotal {
+                    service: creator.service,
+                    id: creator.id,
+                    seconds: streamed.whole_seconds(),
+                })
+                .collect(),
+        })),
+        Err(error) => {
+            error!(?error, "failed to query stream history");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
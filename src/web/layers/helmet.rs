@@ -38,7 +38,7 @@ fn middleware(
         headers.append(
             header::CONTENT_SECURITY_POLICY,
             HeaderValue::from_static(
-                "default-src 'self'; img-src 'self' yt3.ggpht.com static-cdn.jtvnw.net",
+                "default-src 'self'; img-src 'self' yt3.ggpht.com i.ytimg.com static-cdn.jtvnw.net",
             ),
         ); // TODO: report-uri
 
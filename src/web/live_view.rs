@@ -4,11 +4,15 @@ use axum::{extract::WebSocketUpgrade, http::HeaderValue, routing::get, Router};
 use hyper::header;
 use tokio::sync::watch;
 
-use crate::watcher::WatcherDataReceive;
+use crate::{store::HistorySummaryReceive, watcher::WatcherDataReceive};
 
 use super::markup::{dashboard, DashboardProps};
 
-pub fn router(listen: SocketAddr, watcher_data: watch::Receiver<WatcherDataReceive>) -> Router {
+pub fn router(
+    listen: SocketAddr,
+    watcher_data: watch::Receiver<WatcherDataReceive>,
+    history_summary: watch::Receiver<HistorySummaryReceive>,
+) -> Router {
     let view = dioxus_liveview::LiveViewPool::new();
 
     Router::new()
@@ -47,6 +51,7 @@ pub fn router(listen: SocketAddr, watcher_data: watch::Receiver<WatcherDataRecei
                             dashboard,
                             DashboardProps {
                                 watched_data: watcher_data,
+                                history_summary,
                             },
                         )
                         .await;
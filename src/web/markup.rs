@@ -2,17 +2,29 @@ use dioxus::prelude::*;
 use git_version::git_version;
 use tokio::sync::watch;
 
-use crate::watcher::{WatcherData, WatcherDataReceive};
+use crate::{
+    store::HistorySummaryReceive,
+    watcher::{WatcherData, WatcherDataReceive},
+};
 
 use self::creator_card::creator_card;
 use self::date::locale_date;
+use self::lineup::lineup;
+use self::pace::pace;
+use self::progress_chart::progress_chart;
+use self::up_next::up_next;
 
 mod creator_card;
 mod date;
+mod lineup;
+mod pace;
+mod progress_chart;
+mod up_next;
 
 #[derive(Debug)]
 pub struct DashboardProps {
     pub watched_data: watch::Receiver<WatcherDataReceive>,
+    pub history_summary: watch::Receiver<HistorySummaryReceive>,
 }
 
 #[tracing::instrument(skip_all)]
@@ -40,12 +52,41 @@ pub fn dashboard<'s>(cx: Scope<'s, DashboardProps>) -> Element<'s> {
         }
     });
 
+    let history_summary = use_state(cx, || cx.props.history_summary.borrow().clone());
+
+    use_coroutine(cx, {
+        let schedule_update = cx.schedule_update_any();
+        let scope_id = cx.scope_id();
+        let mut history_summary_rx = cx.props.history_summary.clone();
+        let history_summary = history_summary.clone();
+
+        move |_: UnboundedReceiver<()>| async move {
+            loop {
+                *history_summary.make_mut() = history_summary_rx.borrow().clone();
+
+                match history_summary_rx.changed().await {
+                    Ok(()) => {}
+                    // Channel closed
+                    Err(_err) => break,
+                };
+
+                schedule_update(scope_id);
+            }
+        }
+    });
+
     if let Some(WatcherData {
         updated,
         creators,
+        people,
         tiltify,
+        totals,
+        lineup: marathon_lineup,
+        restored,
     }) = watched_data.as_deref()
     {
+        let history_summary = &**history_summary.get();
+
         cx.render(rsx! {
             main {
                 h1 {
@@ -57,23 +98,51 @@ pub fn dashboard<'s>(cx: Scope<'s, DashboardProps>) -> Element<'s> {
                     "Updated: "
                     locale_date { date: updated }
                 }
+                if *restored {
+                    rsx! {
+                        p {
+                            class: "restored",
+                            "The server just restarted, this data may be out of date until the next refresh"
+                        }
+                    }
+                }
                 // p {
                 //     script {
                 //         "document.currentScript.parentElement.appendChild(document.createTextNode(new Date({js_timestamp}).toLocaleString()));"
                 //     }
                 // }
                 section {
-                    p { "Together we have raised ${tiltify.total_amount_raised} out of the ${tiltify.fundraiser_goal_amount} goal" }
+                    p { "Together we have raised {totals.total} out of the {totals.goal} goal" }
+                    ul {
+                        class: "totals",
+                        li { "Tiltify: {totals.tiltify}" }
+                        li { "Twitch Charity: {totals.twitch_charity}" }
+                    }
+                    progress_chart {
+                        samples: &history_summary.amount_raised,
+                        goal: &tiltify.fundraiser_goal_amount,
+                        now: updated,
+                    }
+                    history_summary.pace.as_ref().map(|campaign_pace| rsx! {
+                        pace { pace: campaign_pace }
+                    })
                     pre { serde_json::to_string(tiltify).expect("tiltify should be serializable") }
                 }
+                lineup { lineup: marathon_lineup }
+                section {
+                    h2 { "Scheduled streams" }
+                    up_next { creators: creators }
+                }
                 section {
                     h2 { "Participating Streamers" }
                     div {
                         class: "creators",
-                        creators.iter().map(|creator| {
+                        people.iter().map(|person| {
+                            // A person always has at least one account
+                            let key = &person.accounts[0];
+
                             cx.render(rsx! {
-                                // TODO: show creator platform
-                                creator_card { key: "{creator.id}", creator: creator, }
+                                creator_card { key: "{key}", person: person, creators: creators, streamed_time: &history_summary.streamed_time, }
                             })
                         })
                     }
@@ -96,6 +165,7 @@ pub fn dashboard<'s>(cx: Scope<'s, DashboardProps>) -> Element<'s> {
                     } else {
                         "production"
                     }
+                    a { href: "/api-docs.html", "API" }
                 }
             }
         })
@@ -1,6 +1,12 @@
+use std::collections::HashMap;
+
 use dioxus::prelude::*;
+use time::Duration;
 
-use crate::{model::Creator, web::markup::date::locale_date};
+use crate::{
+    model::{person::Person, ChannelStats, Creator, CreatorKey},
+    web::markup::date::locale_date,
+};
 
 use self::streaming_service::streaming_service;
 
@@ -8,13 +14,48 @@ mod streaming_service;
 
 #[derive(Debug, Props)]
 pub struct Props<'c> {
-    pub creator: &'c Creator,
+    pub person: &'c Person,
+    pub creators: &'c [Creator],
+    pub streamed_time: &'c HashMap<CreatorKey, Duration>,
 }
 
 pub fn creator_card<'s>(cx: Scope<'s, Props<'s>>) -> Element<'s> {
-    let creator = cx.props.creator;
+    let person = cx.props.person;
+
+    let accounts = person
+        .accounts
+        .iter()
+        .filter_map(|account| account.resolve(cx.props.creators))
+        .collect::<Vec<_>>();
+
+    // Prefer a live account for the picture and link
+    let Some(creator) = accounts
+        .iter()
+        .find(|creator| creator.is_live())
+        .or_else(|| accounts.first())
+        .copied()
+    else {
+        return None;
+    };
+
+    let streamed_hours = person
+        .accounts
+        .iter()
+        .filter_map(|account| cx.props.streamed_time.get(account))
+        .copied()
+        .sum::<Duration>()
+        .as_seconds_f64()
+        / 3600.0;
+
+    // Read by follow.js, which subscribes to push notifications for every account
+    let follow_keys = person
+        .accounts
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
 
-    let class = if creator.stream.is_some() {
+    let class = if person.live {
         "creator live"
     } else {
         "creator"
@@ -27,7 +68,7 @@ pub fn creator_card<'s>(cx: Scope<'s, Props<'s>>) -> Element<'s> {
                 class: "icon",
                 draggable: false,
                 src: "{creator.icon_url}",
-                alt: "profile picture for {creator.display_name}",
+                alt: "profile picture for {person.name}",
                 "loading": "lazy"
             }
             h3 {
@@ -35,41 +76,155 @@ pub fn creator_card<'s>(cx: Scope<'s, Props<'s>>) -> Element<'s> {
                 a {
                     href: "{creator.href}",
                     target: "_blank",
-                    "{creator.display_name}"
+                    "{person.name}"
                 }
             }
-            {
-                creator.stream.as_ref().map(|stream| {
-                    rsx! {
-                        div {
-                            class: "stream",
-                            h4 { "Stream" }
+            button {
+                class: "follow",
+                r#type: "button",
+                // Shown by follow.js, if the browser and server support push notifications
+                hidden: true,
+                title: "Get a notification when {person.name} goes live for the cause",
+                "data-creators": "{follow_keys}",
+                "Follow"
+            }
+            accounts.iter().flat_map(|account| &account.streams).map(|stream| {
+                let class = if stream.campaign {
+                    "stream campaign"
+                } else {
+                    "stream"
+                };
+
+                rsx! {
+                    div {
+                        class: class,
+                        h4 { "Stream" }
+                        stream.thumbnail_url.as_ref().map(|thumbnail_url| rsx! {
                             a {
-                                class: "title",
                                 href: "{stream.href}",
                                 target: "_blank",
-                                title: "{stream.title}",
-                                "{stream.title}"
+                                img {
+                                    class: "thumbnail",
+                                    draggable: false,
+                                    src: "{thumbnail_url}",
+                                    alt: "stream preview",
+                                    "loading": "lazy"
+                                }
                             }
+                        })
+                        a {
+                            class: "title",
+                            href: "{stream.href}",
+                            target: "_blank",
+                            title: "{stream.title}",
+                            "{stream.title}"
+                        }
+                        stream.category.as_ref().map(|category| rsx! {
                             p {
-                                "Started: "
-                                locale_date { date: &stream.start_time }
+                                class: "category",
+                                "Playing: {category}"
+                            }
+                        })
+                        p {
+                            "Started: "
+                            locale_date { date: &stream.start_time }
+                        }
+                        p {
+                            "Viewers: "
+                            if let Some(viewers) = stream.viewers {
+                                rsx! { "{viewers}" }
+                            } else {
+                                rsx! { "Hidden By Creator" }
+                            }
+                        }
+                    }
+                }
+            })
+            {
+                accounts.iter().find_map(|account| account.charity.as_ref()).map(|charity| {
+                    rsx! {
+                        div {
+                            class: "charity",
+                            h4 { "Charity" }
+                            a {
+                                href: "{charity.charity_website}",
+                                target: "_blank",
+                                "{charity.charity_name}"
                             }
                             p {
-                                "Viewers: "
-                                if let Some(viewers) = stream.viewers {
-                                    rsx! { "{viewers}" }
-                                } else {
-                                    rsx! { "Hidden By Creator" }
+                                "Raised: {charity.current_amount}"
+                                if let Some(target_amount) = &charity.target_amount {
+                                    rsx! { " of {target_amount}" }
                                 }
                             }
                         }
                     }
                 })
             }
-            streaming_service {
-                service: creator.service
+            if streamed_hours > 0.0 {
+                rsx! {
+                    p {
+                        class: "streamed",
+                        "{streamed_hours:.1} hours streamed for the cause"
+                    }
+                }
+            }
+            ul {
+                class: "stats",
+                accounts.iter().filter_map(|account| Some((account, account.stats.as_ref()?))).map(|(account, stats)| {
+                    let summary = stats_summary(stats);
+
+                    rsx! {
+                        li {
+                            key: "{account.key()}",
+                            title: "{account.service}",
+                            "{summary}"
+                        }
+                    }
+                })
+            }
+            div {
+                class: "services",
+                accounts.iter().map(|account| {
+                    rsx! {
+                        streaming_service {
+                            key: "{account.key()}",
+                            service: account.service,
+                            href: &account.href,
+                            live: account.is_live(),
+                        }
+                    }
+                })
             }
         }
     })
 }
+
+fn stats_summary(stats: &ChannelStats) -> String {
+    [
+        (stats.followers, "followers"),
+        (stats.subscribers, "subscribers"),
+        (stats.views, "views"),
+    ]
+    .into_iter()
+    .filter_map(|(count, label)| Some(format!("{} {label}", compact_count(count?))))
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+/// Abbreviate large counts, e.g. 12.3K
+fn compact_count(count: u64) -> String {
+    const UNITS: [(f64, &str); 3] = [(1e3, "K"), (1e6, "M"), (1e9, "B")];
+
+    if count < 1_000 {
+        return count.to_string();
+    }
+
+    // The unit is picked after rounding, so 999,999 is 1.0M rather than 1000.0K
+    let (scale, unit) = UNITS
+        .into_iter()
+        .find(|(scale, _)| (count as f64 / scale * 10.0).round() < 10_000.0)
+        .unwrap_or(UNITS[2]);
+
+    format!("{:.1}{unit}", count as f64 / scale)
+}
@@ -3,19 +3,25 @@ use dioxus::prelude::*;
 use crate::model::StreamingService;
 
 #[derive(Debug, Props, PartialEq, Eq)]
-pub struct Props {
+pub struct Props<'c> {
     pub service: StreamingService,
+    pub href: &'c str,
+    pub live: bool,
 }
 
-pub fn streaming_service(cx: Scope<Props>) -> Element {
+pub fn streaming_service<'s>(cx: Scope<'s, Props<'s>>) -> Element<'s> {
     let (class, label) = match cx.props.service {
         StreamingService::Twitch => ("twitch", "Twitch"),
         StreamingService::Youtube => ("youtube", "YouTube"),
     };
 
+    let live = if cx.props.live { " live" } else { "" };
+
     cx.render(rsx! {
-        div {
-            class: "service {class}",
+        a {
+            class: "service {class}{live}",
+            href: "{cx.props.href}",
+            target: "_blank",
             "{label}"
         }
     })
@@ -0,0 +1,84 @@
+use dioxus::prelude::*;
+use time::OffsetDateTime;
+
+use crate::{
+    model::lineup::{LineupSlot, SlotStatus},
+    web::markup::date::locale_date,
+};
+
+#[derive(Debug, Props)]
+pub struct Props<'c> {
+    pub lineup: &'c [LineupSlot],
+}
+
+pub fn lineup<'s>(cx: Scope<'s, Props<'s>>) -> Element<'s> {
+    let lineup = cx.props.lineup;
+
+    if lineup.is_empty() {
+        return None;
+    }
+
+    // The watcher data only changes when a creator does, so a slot starting or ending would
+    // go unnoticed if the status was worked out there
+    let now = OffsetDateTime::now_utc();
+    let statuses = lineup.iter().map(|slot| (slot, slot.status(now)));
+
+    let now_on = statuses.clone().filter(|(_, status)| {
+        matches!(
+            status,
+            SlotStatus::OnAir | SlotStatus::Late | SlotStatus::Missing
+        )
+    });
+    let up_next = statuses
+        .filter(|(_, status)| *status == SlotStatus::Upcoming)
+        .min_by_key(|(slot, _)| slot.starts_at);
+
+    cx.render(rsx! {
+        section {
+            class: "lineup",
+            h2 { "Now on" }
+            now_on.map(|(slot, status)| rsx! {
+                lineup_slot { key: "{slot.handle}-{slot.starts_at}", slot: slot, status: status }
+            })
+            h2 { "Up next" }
+            up_next.map(|(slot, status)| rsx! {
+                lineup_slot { slot: slot, status: status }
+            })
+        }
+    })
+}
+
+#[derive(Debug, Props)]
+struct SlotProps<'c> {
+    slot: &'c LineupSlot,
+    status: SlotStatus,
+}
+
+fn lineup_slot<'s>(cx: Scope<'s, SlotProps<'s>>) -> Element<'s> {
+    let slot = cx.props.slot;
+
+    let name = slot.display_name.as_deref().unwrap_or(&slot.handle);
+
+    let (class, label) = match cx.props.status {
+        SlotStatus::Upcoming => ("upcoming", "Upcoming"),
+        SlotStatus::OnAir => ("on-air", "On air"),
+        SlotStatus::Late => ("late", "Running late"),
+        SlotStatus::Missing => ("missing", "Not live"),
+        SlotStatus::Finished => ("finished", "Finished"),
+    };
+
+    cx.render(rsx! {
+        div {
+            class: "slot {class}",
+            strong { "{name}" }
+            " "
+            locale_date { date: &slot.starts_at }
+            " – "
+            locale_date { date: &slot.ends_at }
+            span {
+                class: "status",
+                "{label}"
+            }
+        }
+    })
+}
@@ -0,0 +1,46 @@
+use dioxus::prelude::*;
+
+use crate::{store::pace::Pace, web::markup::date::locale_date};
+
+#[derive(Debug, Props)]
+pub struct Props<'c> {
+    pub pace: &'c Pace,
+}
+
+/// A compact summary of how quickly money is being raised
+pub fn pace<'s>(cx: Scope<'s, Props<'s>>) -> Element<'s> {
+    let pace = cx.props.pace;
+
+    cx.render(rsx! {
+        dl {
+            class: "pace",
+            dt { "Raising" }
+            dd { "{pace.hourly_rate} per hour" }
+            if let Some(per_creator) = &pace.hourly_rate_per_live_creator {
+                rsx! {
+                    dt { "Per live creator" }
+                    dd { "{per_creator} per hour" }
+                }
+            }
+            if let Some(projected_total) = &pace.projected_total {
+                rsx! {
+                    dt { "Projected total" }
+                    dd { "{projected_total}" }
+                }
+            }
+            dt { "Goal" }
+            if pace.goal_reached {
+                rsx! { dd { "Reached!" } }
+            } else if let Some(projected_goal_time) = &pace.projected_goal_time {
+                rsx! {
+                    dd {
+                        "Expected "
+                        locale_date { date: projected_goal_time }
+                    }
+                }
+            } else {
+                rsx! { dd { "Not yet in sight" } }
+            }
+        }
+    })
+}
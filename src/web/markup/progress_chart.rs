@@ -0,0 +1,77 @@
+use std::fmt::Write;
+
+use dioxus::prelude::*;
+use rust_decimal::prelude::ToPrimitive;
+use time::OffsetDateTime;
+
+use crate::{model::money::Money, store::campaign::AmountSample};
+
+const WIDTH: f64 = 600.0;
+const HEIGHT: f64 = 200.0;
+
+#[derive(Debug, Props)]
+pub struct Props<'c> {
+    pub samples: &'c [AmountSample],
+    pub goal: &'c Money,
+    pub now: &'c OffsetDateTime,
+}
+
+/// A line chart of the amount raised over time, drawn against the goal
+pub fn progress_chart<'s>(cx: Scope<'s, Props<'s>>) -> Element<'s> {
+    let samples = cx.props.samples;
+
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+        return None;
+    };
+
+    let to_f64 = |money: &Money| money.amount.to_f64().unwrap_or_default();
+
+    let goal = to_f64(cx.props.goal);
+    let max_amount = samples
+        .iter()
+        .map(|sample| to_f64(&sample.amount))
+        .fold(goal, f64::max)
+        .max(1.0);
+
+    let start = first.time.unix_timestamp() as f64;
+    let span = ((*cx.props.now).max(last.time).unix_timestamp() as f64 - start).max(1.0);
+
+    let x = |time: OffsetDateTime| (time.unix_timestamp() as f64 - start) / span * WIDTH;
+    let y = |amount: f64| HEIGHT - amount / max_amount * HEIGHT;
+
+    // Hold the last amount until now
+    let mut points = String::new();
+    for (time, amount) in samples
+        .iter()
+        .map(|sample| (sample.time, to_f64(&sample.amount)))
+        .chain([(*cx.props.now, to_f64(&last.amount))])
+    {
+        _ = write!(points, "{:.1},{:.1} ", x(time), y(amount));
+    }
+
+    let goal_y = y(goal);
+
+    cx.render(rsx! {
+        figure {
+            class: "progress-chart",
+            svg {
+                "viewBox": "0 0 {WIDTH} {HEIGHT}",
+                "preserveAspectRatio": "none",
+                "role": "img",
+                "aria-label": "amount raised over time",
+                line {
+                    class: "goal",
+                    "x1": "0",
+                    "x2": "{WIDTH}",
+                    "y1": "{goal_y:.1}",
+                    "y2": "{goal_y:.1}",
+                }
+                polyline {
+                    class: "raised",
+                    "points": "{points}",
+                }
+            }
+            figcaption { "{last.amount} raised towards the {cx.props.goal} goal" }
+        }
+    })
+}
@@ -0,0 +1,52 @@
+use dioxus::prelude::*;
+
+use crate::{model::Creator, web::markup::date::locale_date};
+
+#[derive(Debug, Props)]
+pub struct Props<'c> {
+    pub creators: &'c [Creator],
+}
+
+pub fn up_next<'s>(cx: Scope<'s, Props<'s>>) -> Element<'s> {
+    let mut upcoming = cx
+        .props
+        .creators
+        .iter()
+        .flat_map(|creator| {
+            creator
+                .upcoming
+                .iter()
+                .map(move |scheduled| (creator, scheduled))
+        })
+        .collect::<Vec<_>>();
+
+    upcoming.sort_by_key(|(_, scheduled)| scheduled.start_time);
+
+    if upcoming.is_empty() {
+        return cx.render(rsx! {
+            p { "No streams are scheduled yet" }
+        });
+    }
+
+    cx.render(rsx! {
+        ol {
+            class: "up-next",
+            upcoming.into_iter().map(|(creator, scheduled)| {
+                rsx! {
+                    li {
+                        key: "{creator.key()}-{scheduled.href}-{scheduled.start_time}",
+                        locale_date { date: &scheduled.start_time }
+                        " "
+                        strong { "{creator.display_name}" }
+                        ": "
+                        a {
+                            href: "{scheduled.href}",
+                            target: "_blank",
+                            "{scheduled.title}"
+                        }
+                    }
+                }
+            })
+        }
+    })
+}
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use hyper::StatusCode;
+use reqwest::Url;
+use serde::Deserialize;
+use tokio::sync::watch;
+use tracing::{debug, error, warn};
+
+use crate::{
+    model::CreatorKey,
+    notifications::web_push,
+    store::{
+        push::{self, FollowOutcome, PushSubscription},
+        Store,
+    },
+    watcher::WatcherDataReceive,
+};
+
+/// Most creators that can be followed in a single request, a person has a handful of accounts
+const MAX_CREATORS: usize = 16;
+
+#[derive(Clone)]
+struct PushState {
+    store: Store,
+    public_key: Arc<str>,
+    watcher_data: watch::Receiver<WatcherDataReceive>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Follow {
+    subscription: PushSubscription,
+    creators: Vec<CreatorKey>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Unfollow {
+    endpoint: String,
+    creators: Vec<CreatorKey>,
+}
+
+pub fn router(
+    store: Store,
+    public_key: String,
+    watcher_data: watch::Receiver<WatcherDataReceive>,
+) -> Router {
+    Router::new()
+        .route("/public-key", get(public_key_handler))
+        .route("/follows", post(follow).delete(unfollow))
+        .with_state(PushState {
+            store,
+            public_key: public_key.into(),
+            watcher_data,
+        })
+}
+
+/// The key browsers need to subscribe with
+#[axum::debug_handler]
+async fn public_key_handler(State(state): State<PushState>) -> String {
+    state.public_key.to_string()
+}
+
+#[tracing::instrument(skip_all)]
+#[axum::debug_handler]
+async fn follow(State(state): State<PushState>, Json(follow): Json<Follow>) -> StatusCode {
+    if !valid_creators(&follow.creators) || !valid_subscription(&follow.subscription) {
+        debug!("invalid follow request");
+        return StatusCode::BAD_REQUEST;
+    }
+
+    // Only creators of the campaign can be followed
+    match state.watcher_data.borrow().as_deref() {
+        Some(data) => {
+            if !follow
+                .creators
+                .iter()
+                .all(|creator| creator.resolve(&data.creators).is_some())
+            {
+                debug!("follow request for unknown creators");
+                return StatusCode::BAD_REQUEST;
+            }
+        }
+        None => return StatusCode::SERVICE_UNAVAILABLE,
+    }
+
+    let result = state
+        .store
+        .with_connection(move |connection| {
+            push::follow(connection, &follow.subscription, &follow.creators)
+        })
+        .await;
+
+    match result {
+        Ok(FollowOutcome::Followed) => StatusCode::NO_CONTENT,
+        Ok(FollowOutcome::TooManyFollows) => {
+            debug!("push subscription follows too many creators");
+            StatusCode::BAD_REQUEST
+        }
+        Ok(FollowOutcome::TooManySubscriptions) => {
+            warn!("push subscription limit reached, refusing new subscriptions");
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        Err(error) => {
+            error!(?error, "failed to store push subscription");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+#[axum::debug_handler]
+async fn unfollow(State(state): State<PushState>, Json(unfollow): Json<Unfollow>) -> StatusCode {
+    if !valid_creators(&unfollow.creators) {
+        debug!("invalid unfollow request");
+        return StatusCode::BAD_REQUEST;
+    }
+
+    let result = state
+        .store
+        .with_connection(move |connection| {
+            push::unfollow(connection, &unfollow.endpoint, &unfollow.creators)
+        })
+        .await;
+
+    match result {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(error) => {
+            error!(?error, "failed to remove push follows");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+fn valid_creators(creators: &[CreatorKey]) -> bool {
+    !creators.is_empty() && creators.len() <= MAX_CREATORS
+}
+
+/// Only accept what a browser would hand out, since the server will be sending requests to it
+fn valid_subscription(subscription: &PushSubscription) -> bool {
+    let decoded_length = |key: &str| {
+        base64::decode_config(key, base64::URL_SAFE_NO_PAD).map_or(0, |key| key.len())
+    };
+
+    Url::parse(&subscription.endpoint)
+        .map_or(false, |endpoint| web_push::is_push_service(&endpoint))
+        // An uncompressed P-256 public key
+        && decoded_length(&subscription.keys.p256dh) == 65
+        && decoded_length(&subscription.keys.auth) == 16
+}
@@ -0,0 +1,150 @@
+use std::{convert::Infallible, time::Duration};
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive},
+        Sse,
+    },
+};
+use futures::Stream;
+use serde::Deserialize;
+use tokio::sync::watch;
+use tracing::error;
+
+use crate::watcher::{WatcherData, WatcherDataReceive};
+
+/// Comments sent while nothing changes, so proxies do not close an idle connection
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// A `snapshot` event with all the data on every change
+    #[default]
+    Snapshot,
+    /// A `snapshot` event to start from, then `patch` events with a JSON Patch (RFC 6902)
+    /// against the previous event
+    Patch,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    #[serde(default)]
+    format: Format,
+}
+
+struct StreamState {
+    watcher_data: watch::Receiver<WatcherDataReceive>,
+    format: Format,
+    /// The current data has not been looked at yet
+    pending: bool,
+    /// Id of the data the client has
+    last_id: Option<String>,
+    /// The data the client has, to patch against
+    last_value: Option<serde_json::Value>,
+}
+
+/// Stream the dashboard data as server-sent events, as it changes.
+///
+/// Events are identified by the time the data was updated. A client reconnecting with the
+/// `Last-Event-ID` of the current data only gets what changes after, any other client starts
+/// with a full snapshot
+#[tracing::instrument(skip(watcher_data, headers))]
+#[axum::debug_handler]
+pub async fn handler(
+    State(mut watcher_data): State<watch::Receiver<WatcherDataReceive>>,
+    Query(query): Query<StreamQuery>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let current = watcher_data.borrow_and_update().clone();
+
+    let resumed = headers
+        .get("last-event-id")
+        .and_then(|last_id| last_id.to_str().ok())
+        .zip(current.as_deref())
+        .filter(|(last_id, data)| *last_id == event_id(data));
+
+    let (last_id, last_value) = match resumed {
+        Some((last_id, data)) => (
+            Some(last_id.to_owned()),
+            (query.format == Format::Patch)
+                .then(|| serde_json::to_value(data).ok())
+                .flatten(),
+        ),
+        None => (None, None),
+    };
+
+    let state = StreamState {
+        watcher_data,
+        format: query.format,
+        pending: true,
+        last_id,
+        last_value,
+    };
+
+    let events = futures::stream::unfold(state, |mut state| async move {
+        let event = next_event(&mut state).await?;
+        Some((Ok(event), state))
+    });
+
+    Sse::new(events).keep_alive(
+        KeepAlive::new()
+            .interval(HEARTBEAT_INTERVAL)
+            .text("heartbeat"),
+    )
+}
+
+/// Wait for data the client does not have yet, `None` once the watcher stops
+async fn next_event(state: &mut StreamState) -> Option<Event> {
+    loop {
+        if !state.pending {
+            state.watcher_data.changed().await.ok()?;
+        }
+        state.pending = false;
+
+        let Some(data) = state.watcher_data.borrow_and_update().clone() else {
+            continue;
+        };
+
+        let id = event_id(&data);
+
+        if state.last_id.as_ref() == Some(&id) {
+            continue;
+        }
+
+        let value = match serde_json::to_value(&*data) {
+            Ok(value) => value,
+            Err(error) => {
+                error!(?error, "failed to serialize watcher data");
+                continue;
+            }
+        };
+
+        let event = match (state.format, &state.last_value) {
+            (Format::Patch, Some(last_value)) => Event::default()
+                .event("patch")
+                .json_data(json_patch::diff(last_value, &value)),
+            _ => Event::default().event("snapshot").json_data(&value),
+        };
+
+        let event = match event {
+            Ok(event) => event.id(&id),
+            Err(error) => {
+                error!(?error, "failed to serialize event");
+                continue;
+            }
+        };
+
+        state.last_id = Some(id);
+        state.last_value = (state.format == Format::Patch).then_some(value);
+
+        return Some(event);
+    }
+}
+
+/// The data only changes when its content does, so the update time identifies it
+fn event_id(data: &WatcherData) -> String {
+    data.updated.unix_timestamp_nanos().to_string()
+}
@@ -0,0 +1,204 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{body::Bytes, extract::State, routing::post, Router};
+use hmac::{Hmac, Mac};
+use hyper::{HeaderMap, StatusCode};
+use sha2::Sha256;
+use time::{format_description::well_known, OffsetDateTime};
+use tokio::sync::mpsc;
+use tracing::{debug, error, warn};
+
+use crate::watcher::tiltify::{WebhookEvent, WebhookSecret};
+
+/// Maximum age of a webhook before it is considered a replay
+const MAX_TIMESTAMP_SKEW: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone)]
+struct WebhookState {
+    secret: Arc<WebhookSecret>,
+    events: mpsc::Sender<WebhookEvent>,
+}
+
+pub fn router(secret: WebhookSecret, events: mpsc::Sender<WebhookEvent>) -> Router {
+    Router::new()
+        .route("/", post(webhook))
+        .with_state(WebhookState {
+            secret: Arc::new(secret),
+            events,
+        })
+}
+
+#[tracing::instrument(skip_all)]
+#[axum::debug_handler]
+async fn webhook(
+    State(state): State<WebhookState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let signature = headers
+        .get("x-tiltify-signature")
+        .and_then(|value| value.to_str().ok());
+    let timestamp = headers
+        .get("x-tiltify-timestamp")
+        .and_then(|value| value.to_str().ok());
+
+    let (Some(signature), Some(timestamp)) = (signature, timestamp) else {
+        debug!("webhook missing signature headers");
+        return StatusCode::UNAUTHORIZED;
+    };
+
+    if !verify_signature(&state.secret, signature, timestamp, &body) {
+        warn!("webhook signature did not match");
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    // Reject stale (replayed) webhooks
+    match OffsetDateTime::parse(timestamp, &well_known::Rfc3339) {
+        Ok(timestamp) if (OffsetDateTime::now_utc() - timestamp).abs() <= MAX_TIMESTAMP_SKEW => {}
+        Ok(timestamp) => {
+            warn!(%timestamp, "webhook timestamp outside of the allowed window");
+            return StatusCode::UNAUTHORIZED;
+        }
+        Err(error) => {
+            debug!(?error, "webhook timestamp is not a valid rfc3339 date-time");
+            return StatusCode::BAD_REQUEST;
+        }
+    }
+
+    let event: WebhookEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(error) => {
+            warn!(?error, "incompatible json received from tiltify webhook");
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+
+    match state.events.send(event).await {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(error) => {
+            error!(?error, "live watcher is no longer receiving webhook events");
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+}
+
+/// Tiltify signs the string `{timestamp}.{body}` with HMAC-SHA256, and sends the
+/// base64 encoded result
+fn verify_signature(secret: &WebhookSecret, signature: &str, timestamp: &str, body: &[u8]) -> bool {
+    let Ok(signature) = base64::decode(signature) else {
+        return false;
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_str().as_bytes())
+        .expect("hmac should accept keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::Server;
+
+    use super::*;
+
+    const SECRET: &str = "tiltify-webhook-secret";
+    const BODY: &str =
+        r#"{"meta":{"id":"a1b2","event_type":"public:direct:donation_updated"},"data":{}}"#;
+
+    fn sign(timestamp: &str, body: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes())
+            .expect("hmac should accept keys of any length");
+        mac.update(timestamp.as_bytes());
+        mac.update(b".");
+        mac.update(body.as_bytes());
+
+        base64::encode(mac.finalize().into_bytes())
+    }
+
+    /// Post the body to a local instance of the endpoint, returning the response status and
+    /// the event passed on to the watcher, signed properly unless a signature is given
+    async fn post(
+        timestamp: OffsetDateTime,
+        signature: Option<String>,
+    ) -> (StatusCode, Option<WebhookEvent>) {
+        let (sender, mut receiver) = mpsc::channel(1);
+
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(router(WebhookSecret::from_static(SECRET), sender).into_make_service());
+        let address = server.local_addr();
+        tokio::spawn(server);
+
+        let timestamp = timestamp
+            .format(&well_known::Rfc3339)
+            .expect("timestamp should be formattable");
+        let signature = signature.unwrap_or_else(|| sign(&timestamp, BODY));
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{address}/"))
+            .header("x-tiltify-signature", signature)
+            .header("x-tiltify-timestamp", timestamp)
+            .body(BODY)
+            .send()
+            .await
+            .expect("webhook should be delivered");
+
+        (response.status(), receiver.try_recv().ok())
+    }
+
+    #[test]
+    fn verifies_signatures() {
+        let secret = WebhookSecret::from_static(SECRET);
+        let timestamp = "2023-02-18T18:00:00Z";
+        let signature = sign(timestamp, BODY);
+
+        assert!(verify_signature(
+            &secret,
+            &signature,
+            timestamp,
+            BODY.as_bytes()
+        ));
+        assert!(!verify_signature(&secret, &signature, timestamp, b"{}"));
+        assert!(!verify_signature(
+            &secret,
+            &signature,
+            "2023-02-18T18:00:01Z",
+            BODY.as_bytes()
+        ));
+        assert!(!verify_signature(
+            &secret,
+            "not base64!",
+            timestamp,
+            BODY.as_bytes()
+        ));
+    }
+
+    #[tokio::test]
+    async fn accepts_signed_webhooks() {
+        let (status, event) = post(OffsetDateTime::now_utc(), None).await;
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert_eq!(event.map(|event| event.meta.id).as_deref(), Some("a1b2"));
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_signatures() {
+        let (status, event) = post(OffsetDateTime::now_utc(), Some(sign("forged", BODY))).await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(event.is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_stale_timestamps() {
+        let stale = OffsetDateTime::now_utc() - MAX_TIMESTAMP_SKEW - Duration::from_secs(60);
+        let (status, event) = post(stale, None).await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(event.is_none());
+    }
+}
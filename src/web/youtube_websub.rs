@@ -0,0 +1,217 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    routing::get,
+    Router,
+};
+use hmac::{Hmac, Mac};
+use hyper::{HeaderMap, StatusCode};
+use serde::Deserialize;
+use sha1::Sha1;
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    model::StreamingService,
+    watcher::{
+        youtube::{
+            api::ChannelId,
+            websub::{parse_notification, topic_channel_id, GrantedLease, WebSubSecret},
+        },
+        WatcherDataReceive,
+    },
+};
+
+#[derive(Clone)]
+struct WebSubState {
+    secret: Arc<WebSubSecret>,
+    watcher_data: watch::Receiver<WatcherDataReceive>,
+    notifications: mpsc::Sender<ChannelId>,
+    leases: mpsc::Sender<GrantedLease>,
+}
+
+/// What the callback shares with the subscriber
+pub struct WebSubCallback {
+    pub secret: WebSubSecret,
+    /// Leases granted by the hub, so subscriptions are renewed in time
+    pub leases: mpsc::Sender<GrantedLease>,
+}
+
+pub fn router(
+    callback: WebSubCallback,
+    watcher_data: watch::Receiver<WatcherDataReceive>,
+    notifications: mpsc::Sender<ChannelId>,
+) -> Router {
+    Router::new()
+        .route("/", get(verify).post(notification))
+        .with_state(WebSubState {
+            secret: Arc::new(callback.secret),
+            watcher_data,
+            notifications,
+            leases: callback.leases,
+        })
+}
+
+#[derive(Debug, Deserialize)]
+struct Verification {
+    #[serde(rename = "hub.mode")]
+    mode: String,
+    #[serde(rename = "hub.topic")]
+    topic: String,
+    #[serde(rename = "hub.challenge")]
+    challenge: String,
+    #[serde(rename = "hub.lease_seconds")]
+    lease_seconds: Option<u64>,
+}
+
+/// Confirm subscription requests from the hub by echoing the challenge
+#[tracing::instrument(skip(state))]
+#[axum::debug_handler]
+async fn verify(
+    State(state): State<WebSubState>,
+    Query(verification): Query<Verification>,
+) -> Result<String, StatusCode> {
+    let channel_id = topic_channel_id(&verification.topic).ok_or(StatusCode::NOT_FOUND)?;
+
+    let is_roster_channel = state.watcher_data.borrow().iter().any(|data| {
+        data.creators
+            .iter()
+            .any(|creator| creator.is(StreamingService::Youtube, channel_id.as_str()))
+    });
+
+    // Only confirm subscriptions to channels on the roster, but allow any unsubscription
+    match verification.mode.as_str() {
+        "subscribe" if is_roster_channel => {}
+        "unsubscribe" => {}
+        mode => {
+            warn!(mode, %channel_id, "refusing websub verification");
+            return Err(StatusCode::NOT_FOUND);
+        }
+    }
+
+    info!(
+        mode = %verification.mode,
+        %channel_id,
+        lease_seconds = ?verification.lease_seconds,
+        "websub subscription verified"
+    );
+
+    if let ("subscribe", Some(lease_seconds)) =
+        (verification.mode.as_str(), verification.lease_seconds)
+    {
+        let granted = GrantedLease {
+            channel_id,
+            lease: Duration::from_secs(lease_seconds),
+        };
+
+        // The subscriber falls back to the lease it asked for
+        if let Err(error) = state.leases.try_send(granted) {
+            warn!(?error, "failed to pass on the granted websub lease");
+        }
+    }
+
+    Ok(verification.challenge)
+}
+
+#[tracing::instrument(skip_all)]
+#[axum::debug_handler]
+async fn notification(
+    State(state): State<WebSubState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    // Hubs expect a success response even if the signature does not match,
+    // the notification should just be ignored
+    let signature = headers
+        .get("x-hub-signature")
+        .and_then(|value| value.to_str().ok());
+
+    if !signature.map_or(false, |signature| {
+        verify_signature(&state.secret, signature, &body)
+    }) {
+        warn!("websub notification signature did not match");
+        return StatusCode::NO_CONTENT;
+    }
+
+    let channel_ids = match std::str::from_utf8(&body)
+        .map_err(color_eyre::Report::from)
+        .and_then(parse_notification)
+    {
+        Ok(channel_ids) => channel_ids,
+        Err(error) => {
+            warn!(?error, "failed to parse websub notification");
+            return StatusCode::NO_CONTENT;
+        }
+    };
+
+    for channel_id in channel_ids {
+        debug!(%channel_id, "websub notification received");
+
+        if let Err(error) = state.notifications.send(channel_id).await {
+            error!(
+                ?error,
+                "live watcher is no longer receiving websub notifications"
+            );
+            return StatusCode::SERVICE_UNAVAILABLE;
+        }
+    }
+
+    StatusCode::NO_CONTENT
+}
+
+/// The hub signs the body with HMAC-SHA1, sent as `sha1={hex}`
+fn verify_signature(secret: &WebSubSecret, signature: &str, body: &[u8]) -> bool {
+    let Some(Ok(signature)) = signature.strip_prefix("sha1=").map(hex::decode) else {
+        return false;
+    };
+
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret.as_str().as_bytes())
+        .expect("hmac should accept keys of any length");
+    mac.update(body);
+
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "websub-secret";
+    const BODY: &[u8] = b"<feed><entry><yt:videoId>dQw4w9WgXcQ</yt:videoId></entry></feed>";
+
+    fn sign(body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha1>::new_from_slice(SECRET.as_bytes())
+            .expect("hmac should accept keys of any length");
+        mac.update(body);
+
+        format!("sha1={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn accepts_valid_signatures() {
+        let secret = WebSubSecret::from_static(SECRET);
+
+        assert!(verify_signature(&secret, &sign(BODY), BODY));
+    }
+
+    #[test]
+    fn rejects_tampered_bodies() {
+        let secret = WebSubSecret::from_static(SECRET);
+
+        assert!(!verify_signature(&secret, &sign(BODY), b"<feed></feed>"));
+    }
+
+    #[test]
+    fn rejects_signatures_without_prefix() {
+        let secret = WebSubSecret::from_static(SECRET);
+        let signature = sign(BODY);
+
+        assert!(!verify_signature(
+            &secret,
+            signature.trim_start_matches("sha1="),
+            BODY
+        ));
+    }
+}
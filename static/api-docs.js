@@ -0,0 +1,169 @@
+/**
+ * Renders the OpenAPI document of the public API as a readable page
+ */
+
+const SCHEMA_PREFIX = "#/components/schemas/";
+
+/**
+ * @param {string} tag
+ * @param {Record<string, string>} attributes
+ * @param {(Node | string)[]} children
+ */
+function element(tag, attributes = {}, children = []) {
+    const element = document.createElement(tag);
+
+    for (const [name, value] of Object.entries(attributes)) {
+        element.setAttribute(name, value);
+    }
+
+    element.append(...children);
+    return element;
+}
+
+/**
+ * Describe a schema in a few words, linking to referenced schemas
+ *
+ * @param {any} schema
+ * @returns {(Node | string)[]}
+ */
+function describe_type(schema) {
+    if (schema === undefined) {
+        return ["any"];
+    }
+
+    if (schema.$ref) {
+        const name = schema.$ref.slice(SCHEMA_PREFIX.length);
+        return [element("a", { href: `#schema-${name}` }, [name])];
+    }
+
+    // Nullable references are wrapped
+    if (schema.allOf?.length === 1) {
+        return describe_type(schema.allOf[0]);
+    }
+
+    if (schema.type === "array") {
+        return ["array of ", ...describe_type(schema.items)];
+    }
+
+    if (schema.enum) {
+        return [`one of ${schema.enum.map((/** @type {string} */ value) => `"${value}"`).join(", ")}`];
+    }
+
+    return [schema.format ? `${schema.type} (${schema.format})` : schema.type];
+}
+
+/**
+ * @param {any} schema
+ * @param {boolean} required
+ */
+function describe_field(schema, required) {
+    const notes = [];
+
+    if (schema.nullable) {
+        notes.push("nullable");
+    }
+
+    if (!required) {
+        notes.push("optional");
+    }
+
+    return notes.length === 0 ? "" : ` (${notes.join(", ")})`;
+}
+
+/**
+ * @param {[string, any][]} rows
+ * @param {(row: [string, any]) => (Node | string)[][]} cells
+ * @param {string[]} headings
+ */
+function table(rows, cells, headings) {
+    return element("table", {}, [
+        element("thead", {}, [
+            element("tr", {}, headings.map((heading) => element("th", {}, [heading]))),
+        ]),
+        element(
+            "tbody",
+            {},
+            rows.map((row) =>
+                element("tr", {}, cells(row).map((cell) => element("td", {}, cell)))
+            )
+        ),
+    ]);
+}
+
+/** @param {any} document */
+function render_operations(document) {
+    const operations = /** @type {HTMLElement} */ (window.document.getElementById("operations"));
+    operations.append(element("h2", {}, ["Endpoints"]));
+
+    for (const [path, methods] of Object.entries(document.paths)) {
+        for (const [method, operation] of Object.entries(/** @type {any} */ (methods))) {
+            const parameters = operation.parameters ?? [];
+
+            operations.append(
+                element("article", { class: "operation" }, [
+                    element("h3", {}, [element("code", {}, [`${method.toUpperCase()} ${path}`])]),
+                    element("p", {}, [operation.description ?? ""]),
+                    parameters.length === 0
+                        ? ""
+                        : table(
+                              parameters.map((/** @type {any} */ parameter) => [parameter.name, parameter]),
+                              ([name, parameter]) => [
+                                  [element("code", {}, [name])],
+                                  [parameter.in],
+                                  [...describe_type(parameter.schema), describe_field(parameter.schema, parameter.required)],
+                                  [parameter.description ?? ""],
+                              ],
+                              ["Parameter", "In", "Type", "Description"]
+                          ),
+                    table(
+                        Object.entries(operation.responses),
+                        ([status, response]) => [
+                            [status],
+                            response.content
+                                ? describe_type(response.content["application/json"].schema)
+                                : ["none"],
+                            [response.description],
+                        ],
+                        ["Status", "Body", "Description"]
+                    ),
+                ])
+            );
+        }
+    }
+}
+
+/** @param {any} document */
+function render_schemas(document) {
+    const schemas = /** @type {HTMLElement} */ (window.document.getElementById("schemas"));
+    schemas.append(element("h2", {}, ["Types"]));
+
+    for (const [name, schema] of Object.entries(document.components.schemas)) {
+        const required = new Set(schema.required ?? []);
+
+        schemas.append(
+            element("article", { class: "schema", id: `schema-${name}` }, [
+                element("h3", {}, [element("code", {}, [name])]),
+                element("p", {}, [schema.description ?? ""]),
+                schema.properties
+                    ? table(
+                          Object.entries(schema.properties),
+                          ([field, property]) => [
+                              [element("code", {}, [field])],
+                              [...describe_type(property), describe_field(property, required.has(field))],
+                              [property.description ?? ""],
+                          ],
+                          ["Field", "Type", "Description"]
+                      )
+                    : element("p", {}, describe_type(schema)),
+            ])
+        );
+    }
+}
+
+fetch("/api/openapi.json")
+    .then((response) => response.json())
+    .then((document) => {
+        render_operations(document);
+        render_schemas(document);
+    })
+    .catch((error) => console.error("failed to load the OpenAPI document", error));
@@ -0,0 +1,146 @@
+/**
+ * Follow buttons on creator cards, which subscribe the browser to push notifications
+ * for when the creator goes live
+ */
+
+const FOLLOWING_STORAGE_KEY = "following";
+
+/** @type {Promise<string | null>} */
+const public_key = (async () => {
+    if (!("serviceWorker" in navigator) || !("PushManager" in window)) {
+        return null;
+    }
+
+    const response = await fetch("/api/push/public-key");
+
+    // Push notifications are not configured on the server
+    if (!response.ok) {
+        return null;
+    }
+
+    return response.text();
+})().catch((error) => {
+    console.warn("push notifications are unavailable", error);
+    return null;
+});
+
+/** @returns {Set<string>} */
+function following() {
+    return new Set(JSON.parse(localStorage.getItem(FOLLOWING_STORAGE_KEY) ?? "[]"));
+}
+
+/** @param {Set<string>} creators */
+function set_following(creators) {
+    localStorage.setItem(FOLLOWING_STORAGE_KEY, JSON.stringify([...creators]));
+}
+
+/** @param {string} base64url */
+function decode_base64url(base64url) {
+    const base64 = base64url.replace(/-/g, "+").replace(/_/g, "/");
+    return Uint8Array.from(atob(base64), (character) => character.charCodeAt(0));
+}
+
+/** @param {HTMLButtonElement} button */
+function creators_of(button) {
+    return (button.getAttribute("data-creators") ?? "").split(",").filter(Boolean);
+}
+
+/** @param {HTMLButtonElement} button */
+function update_button(button) {
+    const current = following();
+    const is_following = creators_of(button).some((creator) => current.has(creator));
+
+    button.hidden = false;
+    button.classList.toggle("following", is_following);
+    button.setAttribute("aria-pressed", String(is_following));
+    button.textContent = is_following ? "Following" : "Follow";
+}
+
+/** @param {string} key */
+async function push_subscription(key) {
+    const registration = await navigator.serviceWorker.register("/service-worker.js");
+    await navigator.serviceWorker.ready;
+
+    const existing = await registration.pushManager.getSubscription();
+    if (existing !== null) {
+        return existing;
+    }
+
+    return registration.pushManager.subscribe({
+        userVisibleOnly: true,
+        applicationServerKey: decode_base64url(key),
+    });
+}
+
+/** @param {HTMLButtonElement} button */
+async function toggle_follow(button) {
+    const key = await public_key;
+    if (key === null) {
+        return;
+    }
+
+    const creators = creators_of(button);
+    const current = following();
+    const is_following = creators.some((creator) => current.has(creator));
+
+    if (!is_following && (await Notification.requestPermission()) !== "granted") {
+        return;
+    }
+
+    const subscription = await push_subscription(key);
+    const keys = creators.map((creator) => {
+        const [service, ...id] = creator.split(":");
+        return { service, id: id.join(":") };
+    });
+
+    const response = await fetch("/api/push/follows", {
+        method: is_following ? "DELETE" : "POST",
+        headers: { "content-type": "application/json" },
+        body: JSON.stringify(
+            is_following
+                ? { endpoint: subscription.endpoint, creators: keys }
+                : { subscription: subscription.toJSON(), creators: keys }
+        ),
+    });
+
+    if (!response.ok) {
+        console.warn("failed to update follows", response.status);
+        return;
+    }
+
+    for (const creator of creators) {
+        if (is_following) {
+            current.delete(creator);
+        } else {
+            current.add(creator);
+        }
+    }
+    set_following(current);
+
+    document.querySelectorAll("button.follow").forEach(update_button);
+}
+
+document.addEventListener("click", (event) => {
+    if (event.target instanceof HTMLButtonElement && event.target.matches("button.follow")) {
+        toggle_follow(event.target).catch((error) =>
+            console.warn("failed to follow creator", error)
+        );
+    }
+});
+
+// The live view adds and replaces cards as creators change
+public_key.then((key) => {
+    if (key === null) {
+        return;
+    }
+
+    document.querySelectorAll("button.follow").forEach(update_button);
+
+    new MutationObserver((mutations) => {
+        for (const mutation of mutations) {
+            if (mutation.target instanceof HTMLElement) {
+                mutation.target.querySelectorAll("button.follow").forEach(update_button);
+            }
+        }
+    }).observe(document, { childList: true, subtree: true });
+});
@@ -0,0 +1,26 @@
+/**
+ * Shows the push notifications sent when a followed creator goes live
+ */
+
+self.addEventListener("push", (event) => {
+    if (event.data === null) {
+        return;
+    }
+
+    const message = event.data.json();
+
+    event.waitUntil(
+        self.registration.showNotification(message.title, {
+            body: message.body,
+            icon: message.icon,
+            tag: message.tag,
+            data: { url: message.url },
+        })
+    );
+});
+
+self.addEventListener("notificationclick", (event) => {
+    event.notification.close();
+
+    event.waitUntil(self.clients.openWindow(event.notification.data.url));
+});